@@ -0,0 +1,9 @@
+// Writes the TypeScript declarations of the atlas messages next to the
+// wasm-pack output, so the page and worker are checked against the Rust types.
+import { readFile, writeFile } from 'node:fs/promises';
+import init, { typeDeclarations } from '../atlas/client/atlas_client.js';
+
+const atlas = new URL('../atlas/', import.meta.url);
+
+await init(await readFile(new URL('client/atlas_client_bg.wasm', atlas)));
+await writeFile(new URL('comms.d.ts', atlas), typeDeclarations());
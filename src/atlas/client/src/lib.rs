@@ -1,135 +1,298 @@
 use atlas_comms::{
     client::ClientMessage,
-    port::{Listener, Port},
+    handshake::{HandshakeError, Hello},
+    heartbeat::{self, ConnectionState},
+    port::{Listener, Port, TypedPort},
+    rpc::{self, RpcClient},
     server::{ServerEvent, ServerMessage, ServerResponse},
-    Payload,
+    typescript::TypeScript,
+};
+use log::{trace, warn};
+use std::{
+    cell::{Cell, RefCell},
+    future::{self, Future},
+    pin::Pin,
+    rc::Rc,
+    task::Poll,
+    time::Duration,
 };
-use log::trace;
 use tokio::sync::mpsc::channel;
 use wasm_bindgen::prelude::*;
-use web_sys::{BroadcastChannel, MessageChannel, MessageEvent, OffscreenCanvas, Worker};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    AbortSignal, BroadcastChannel, ErrorEvent, MessageChannel, MessageEvent, OffscreenCanvas,
+    Worker,
+};
 
 pub use atlas_comms::init_output;
 
+// Written to atlas/comms.d.ts by pnpm build:atlas-types.
+#[wasm_bindgen(js_name = typeDeclarations)]
+pub fn type_declarations() -> String {
+    atlas_comms::typescript::declarations()
+}
+
 const BUS_PREFIX: &str = "atlas_bus";
 
+#[wasm_bindgen(typescript_custom_section)]
+const REQUEST_OPTIONS: &str = r#"
+export interface RequestOptions {
+    // Milliseconds, Infinity waits forever. Defaults to the client's timeout.
+    timeout?: number;
+    signal?: AbortSignal;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    // Passed last to every request, like fetch's init. Requests that time out
+    // reject with a TimeoutError and aborted ones with an AbortError.
+    #[wasm_bindgen(typescript_type = "RequestOptions")]
+    pub type RequestOptions;
+
+    #[wasm_bindgen(method, getter)]
+    fn timeout(this: &RequestOptions) -> Option<f64>;
+
+    #[wasm_bindgen(method, getter)]
+    fn signal(this: &RequestOptions) -> Option<AbortSignal>;
+}
+
+// Pushes events to the client, which only sends it heartbeats.
+type Wire = TypedPort<ServerEvent, ClientMessage>;
+
 #[wasm_bindgen]
 pub struct AtlasClient {
     _server: Worker,
-    pipe: Port,
-    wire: Option<(Port, Listener<'static>)>,
+    pipe: Rc<RpcClient<ClientMessage, ServerResponse>>,
+    wire: Rc<RefCell<Option<(Wire, Listener)>>>,
+    connection: Rc<Cell<ConnectionState>>,
     bus_id: String,
 }
 
 #[wasm_bindgen]
 impl AtlasClient {
-    #[wasm_bindgen(constructor)]
-    pub fn new(server: Worker) -> Self {
-        Self {
-            _server: server.clone(),
-            wire: None,
-            pipe: Port::wrap(Box::new(server)),
+    // Resolves once the server announced itself and turned out compatible,
+    // nothing is sent to it before that. Rejects if the server says anything
+    // else first, fails to start, or stays silent for rpc::DEFAULT_TIMEOUT.
+    pub async fn connect(server: Worker) -> Result<AtlasClient, JsValue> {
+        let port = Port::wrap(Box::new(server.clone()));
+        let (tx, mut rx) = channel::<Result<Hello, HandshakeError>>(1);
+
+        let on_message = tx.clone();
+        let listener = port
+            .add_listener(Closure::new(move |event: MessageEvent| {
+                let hello = Hello::from_value(&event.data()).ok_or(HandshakeError::Unexpected);
+                let _ = on_message.try_send(hello);
+            }))
+            .map_err(JsError::from)?;
+        let on_error = Closure::<dyn Fn(JsValue)>::new(move |event: JsValue| {
+            let message = event
+                .dyn_ref::<ErrorEvent>()
+                .map(ErrorEvent::message)
+                .unwrap_or_else(|| "failed to load".into());
+            let _ = tx.try_send(Err(HandshakeError::Worker(message)));
+        });
+        server.add_event_listener_with_callback("error", on_error.as_ref().unchecked_ref())?;
+
+        let mut timeout = rpc::sleep(rpc::DEFAULT_TIMEOUT);
+        let remote = future::poll_fn(|cx| {
+            if let Poll::Ready(hello) = rx.poll_recv(cx) {
+                return Poll::Ready(hello.expect("Hello channel should not be closed."));
+            }
+            Pin::new(&mut timeout)
+                .poll(cx)
+                .map(|()| Err(HandshakeError::Timeout(rpc::DEFAULT_TIMEOUT)))
+        })
+        .await;
+        listener.clear().map_err(JsError::from)?;
+        server.remove_event_listener_with_callback("error", on_error.as_ref().unchecked_ref())?;
+        let remote = remote.map_err(JsError::from)?;
+        trace!("[client]<-server: {:?}", remote);
+
+        atlas_comms::hello()
+            .check(&remote, rpc::CAPABILITIES)
+            .map_err(JsError::from)?;
+
+        Ok(Self {
+            _server: server,
+            wire: Rc::default(),
+            pipe: Rc::new(RpcClient::new(port).map_err(JsError::from)?),
+            connection: Rc::new(Cell::new(ConnectionState::Connected)),
             bus_id: format!("{}#{}", BUS_PREFIX, rand::random::<u8>()),
-        }
+        })
     }
 
-    pub async fn listen(&mut self) {
-        let channel = MessageChannel::new().unwrap();
+    // Milliseconds requests wait for by default, Infinity waits forever.
+    #[wasm_bindgen(js_name = setTimeout)]
+    pub fn set_timeout(&self, timeout: f64) {
+        self.pipe.set_timeout(millis(timeout));
+    }
+
+    // Beats every interval milliseconds, 5 seconds by default, until the client
+    // is freed. on_change is called with "connected", "unresponsive" or "lost"
+    // as the server's state changes. Beats also go over the wire, which the
+    // server drops once they stop for a few intervals.
+    pub fn heartbeat(&self, on_change: Option<js_sys::Function>, interval: Option<f64>) {
+        let interval = interval.and_then(millis).unwrap_or(heartbeat::INTERVAL);
+        let beat = ClientMessage::Beat(interval.as_millis().try_into().unwrap_or(u32::MAX));
+        let wire = Rc::downgrade(&self.wire);
+        let ping = move || {
+            if let Some(wire) = wire.upgrade() {
+                if let Some((wire, _)) = &*wire.borrow() {
+                    if let Err(error) = wire.send(beat.clone()) {
+                        warn!("[client]->wire: {}", error);
+                    }
+                }
+            }
+            ClientMessage::Ping
+        };
+
+        let connection = Rc::clone(&self.connection);
+        let on_change = move |state: ConnectionState| {
+            trace!("[client]: server is {}", state);
+            connection.set(state);
+            if let Some(on_change) = &on_change {
+                let _ = on_change.call1(&JsValue::undefined(), &state.to_string().into());
+            }
+        };
+
+        wasm_bindgen_futures::spawn_local(heartbeat::run(
+            Rc::downgrade(&self.pipe),
+            ping,
+            interval,
+            on_change,
+        ));
+    }
+
+    // "connected", "unresponsive" or "lost", as of the last heartbeat.
+    #[wasm_bindgen(getter)]
+    pub fn connection(&self) -> String {
+        self.connection.get().to_string()
+    }
+
+    pub async fn listen(&mut self, options: Option<RequestOptions>) -> Result<(), JsValue> {
+        let channel = MessageChannel::new()?;
         let (rx, tx) = (channel.port1(), channel.port2());
+        let bus = BroadcastChannel::new(&self.bus_id)?;
 
-        let res = self.request(ClientMessage::WireUp(tx)).await;
-        if let ServerResponse::Ok(ServerMessage::Ok) = res {
-            let wire = Port::wrap(Box::new(rx));
-            let bus_id = self.bus_id.clone();
-            let handle = wire.add_listener(Closure::new(move |event: MessageEvent| {
-                let event: ServerEvent = event.data().try_into().unwrap();
+        self.request(ClientMessage::WireUp(tx), options).await?;
+        let wire = TypedPort::new(Port::wrap(Box::new(rx)));
+        let handle = wire
+            .add_listener(move |event| {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(error) => {
+                        warn!("[··wire]<-server: {}", error);
+                        return;
+                    }
+                };
                 trace!("[··wire]<-server: {:?}", event);
 
-                let channel = BroadcastChannel::new(&bus_id).unwrap();
-
-                // TODO This shouldn't be manual.
-                // The event data should probably be generic over #[wasm_bindgen]
-                // structs so observables can subscribe with a function that
-                // receives a typed payload.
-                let payload = js_sys::Array::new();
-                match event {
-                    ServerEvent::Count(value) => {
-                        payload.push(&JsValue::from(stringify!(ServerEvent::Count)));
-                        payload.push(&JsValue::from(value));
-                    }
+                if let ServerEvent::Unknown = event {
+                    return;
                 }
 
-                channel.post_message(&payload).unwrap();
-            }));
+                // A [tag, value] pair, as declared by ServerEvent in
+                // atlas/comms.d.ts, which is what observers are typed against.
+                match event.into_ts::<JsValue>() {
+                    Ok(payload) => {
+                        if let Err(error) = bus.post_message(&payload) {
+                            warn!("[client]->bus: {:?}", error);
+                        }
+                    }
+                    Err(error) => warn!("[client]->bus: {}", error),
+                }
+            })
+            .map_err(JsError::from)?;
 
-            // From wasm_bindgen:
-            // structs with #[wasm_bindgen] cannot have lifetime or type
-            // parameters currently
-            //
-            // Instead of calling mem::forget(handle), transmute the lifetime to
-            // 'static so we can still store it. There might be a better way of
-            // handling this, but it's not a priority since right now there's
-            // only one client (and it's likely that's always gonna be the case)
-            let handle = unsafe { std::mem::transmute(handle) };
+        *self.wire.borrow_mut() = Some((wire, handle));
 
-            self.wire = Some((wire, handle));
-        }
+        Ok(())
     }
 
-    pub async fn attach(&self, surface: OffscreenCanvas) {
-        self.request(ClientMessage::Attach(surface)).await;
+    pub async fn attach(
+        &self,
+        surface: OffscreenCanvas,
+        options: Option<RequestOptions>,
+    ) -> Result<(), JsValue> {
+        self.request(ClientMessage::Attach(surface), options)
+            .await?;
+        Ok(())
     }
 
-    pub async fn ping(&self) {
-        self.request(ClientMessage::Ping).await;
+    pub async fn ping(&self, options: Option<RequestOptions>) -> Result<(), JsValue> {
+        self.request(ClientMessage::Ping, options).await?;
+        Ok(())
     }
 
-    pub async fn query(&self) {
-        self.request(ClientMessage::Query).await;
+    pub async fn query(&self, options: Option<RequestOptions>) -> Result<(), JsValue> {
+        self.request(ClientMessage::Query, options).await?;
+        Ok(())
     }
 
-    pub async fn inc(&self) {
-        self.request(ClientMessage::Inc).await;
+    pub async fn inc(&self, options: Option<RequestOptions>) -> Result<(), JsValue> {
+        self.request(ClientMessage::Inc, options).await?;
+        Ok(())
     }
 
-    pub async fn dec(&self) {
-        self.request(ClientMessage::Dec).await;
+    pub async fn dec(&self, options: Option<RequestOptions>) -> Result<(), JsValue> {
+        self.request(ClientMessage::Dec, options).await?;
+        Ok(())
     }
 
-    async fn request(&self, message: ClientMessage) -> ServerResponse {
-        let (tx, mut rx) = channel::<ServerResponse>(1);
-        let id: u8 = rand::random();
+    async fn request(
+        &self,
+        message: ClientMessage,
+        options: Option<RequestOptions>,
+    ) -> Result<ServerMessage, JsValue> {
+        let (timeout, signal) = match &options {
+            Some(options) => (
+                options.timeout().map_or(self.pipe.timeout(), millis),
+                options.signal(),
+            ),
+            None => (self.pipe.timeout(), None),
+        };
 
-        let listener = self
+        let response = self
             .pipe
-            .add_listener(Closure::new(move |event: MessageEvent| {
-                let payload: Payload<ServerResponse> = event.data().try_into().unwrap();
-                trace!("[client]<-server: {:?}", payload);
-
-                if payload.id == id {
-                    tx.try_send(payload.message)
-                        .expect("Request channel should not be closed.");
-                }
-            }));
-
-        let payload = Payload { id, message };
-        self.pipe.send(payload);
-
-        let response = rx.recv().await.expect("Should post a valid response.");
-        listener.clear();
-
-        response
+            .request_with(message, timeout, aborted(signal))
+            .await?;
+        trace!("[client]<-server: {:?}", response);
+        match response {
+            ServerResponse::Ok(message) => Ok(message),
+            ServerResponse::Err(error) => Err(JsError::from(error).into()),
+        }
     }
 
-    pub fn observe(&mut self, observable: String) -> Observable {
-        Observable {
+    pub fn observe(&mut self, observable: String) -> Result<Observable, JsValue> {
+        Ok(Observable {
             id: observable,
-            channel: BroadcastChannel::new(&self.bus_id).unwrap(),
+            channel: BroadcastChannel::new(&self.bus_id)?,
             listeners: Vec::new(),
-        }
+        })
     }
 }
 
+fn millis(timeout: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(timeout.max(0.0) / 1000.0).ok()
+}
+
+// Resolves once the signal is aborted, never without one.
+async fn aborted(signal: Option<AbortSignal>) {
+    let Some(signal) = signal else {
+        return future::pending().await;
+    };
+
+    let aborted = js_sys::Promise::new(&mut |resolve, reject| {
+        if signal.aborted() {
+            let _ = resolve.call0(&JsValue::undefined());
+        } else if let Err(error) = signal.add_event_listener_with_callback("abort", &resolve) {
+            let _ = reject.call1(&JsValue::undefined(), &error);
+        }
+    });
+    let _ = JsFuture::from(aborted).await;
+}
+
 #[wasm_bindgen]
 pub struct Observable {
     id: String,
@@ -144,16 +307,15 @@ impl Observable {
         let listener = Closure::<dyn Fn(MessageEvent)>::new(move |event: MessageEvent| {
             let event: js_sys::Array = event.data().into();
 
-            let event_id = event.get(0).as_string().unwrap();
-            if event_id == id {
-                on_change
-                    .call1(&JsValue::undefined(), &event.get(1))
-                    .unwrap();
+            if event.get(0).as_string().as_ref() != Some(&id) {
+                return;
+            }
+            if let Err(error) = on_change.call1(&JsValue::undefined(), &event.get(1)) {
+                warn!("[client]<-bus: observer of {} threw {:?}", id, error);
             }
         });
         self.channel
-            .add_event_listener_with_callback("message", listener.as_ref().unchecked_ref())
-            .unwrap();
+            .add_event_listener_with_callback("message", listener.as_ref().unchecked_ref())?;
         self.listeners.push(listener);
 
         let listener = self.listeners.last().unwrap();
@@ -161,9 +323,11 @@ impl Observable {
         let listener_handle = listener_handle.clone();
         let channel_handle = self.channel.clone();
         let unsubscribe = Closure::<dyn Fn()>::new(move || {
-            channel_handle
-                .remove_event_listener_with_callback("message", &listener_handle)
-                .unwrap();
+            if let Err(error) =
+                channel_handle.remove_event_listener_with_callback("message", &listener_handle)
+            {
+                warn!("[client]<-bus: {:?}", error);
+            }
         });
         Ok(unsubscribe.into_js_value())
     }
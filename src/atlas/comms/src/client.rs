@@ -1,13 +1,53 @@
 use atlas_comms_derive::Shareable;
-use wasm_bindgen::JsValue;
 use web_sys::{MessagePort, OffscreenCanvas};
 
-#[derive(Debug, Shareable)]
+#[derive(Clone, Debug, PartialEq, Shareable)]
 pub enum ClientMessage {
     Ping,
     Query,
     Inc,
     Dec,
-    Attach(#[shareable(repr = "raw", transfer)] OffscreenCanvas),
-    WireUp(#[shareable(repr = "raw", transfer)] MessagePort),
+    Attach(
+        #[shareable(
+            repr = "raw",
+            transfer,
+            validate = "canvas_size",
+            strategy = "arbitrary_canvas"
+        )]
+        OffscreenCanvas,
+    ),
+    WireUp(#[shareable(repr = "raw", transfer, strategy = "arbitrary_port")] MessagePort),
+    // Sent over the wire every so many milliseconds while the client is alive.
+    Beat(#[shareable(repr = "serde")] u32),
+    // Sent by a newer client.
+    #[shareable(other)]
+    Unknown,
+}
+
+// The largest texture every wgpu backend is guaranteed to support.
+const MAX_CANVAS_SIZE: u32 = 8192;
+
+fn canvas_size(canvas: &OffscreenCanvas) -> Result<(), String> {
+    let (width, height) = (canvas.width(), canvas.height());
+    if (1..=MAX_CANVAS_SIZE).contains(&width) && (1..=MAX_CANVAS_SIZE).contains(&height) {
+        Ok(())
+    } else {
+        Err(format!(
+            "canvas is {}x{}, expected between 1x1 and {}x{}",
+            width, height, MAX_CANVAS_SIZE, MAX_CANVAS_SIZE
+        ))
+    }
+}
+
+#[cfg(feature = "proptest")]
+fn arbitrary_canvas() -> impl proptest::strategy::Strategy<Value = OffscreenCanvas> {
+    use proptest::strategy::Strategy;
+
+    (1..=MAX_CANVAS_SIZE, 1..=MAX_CANVAS_SIZE)
+        .prop_map(|(width, height)| OffscreenCanvas::new(width, height).unwrap())
+}
+
+#[cfg(feature = "proptest")]
+fn arbitrary_port() -> impl proptest::strategy::Strategy<Value = MessagePort> {
+    proptest::strategy::LazyJust::new(|| web_sys::MessageChannel::new().unwrap().port1())
 }
@@ -0,0 +1,166 @@
+use crate::schema::{Manifest, SchemaMismatch};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+use wasm_bindgen::JsValue;
+
+// Bumped when the handshake or the RPC framing changes, the schema hash only
+// covers the messages themselves.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// Build features that change the wire layout, both sides need the same ones.
+const WIRE_FEATURES: &[&str] = &["verification"];
+
+// Announced by the server once it listens, the client checks it before sending
+// anything. A plain object like the manifest, so any version can read it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    pub protocol: u32,
+    pub features: Vec<String>,
+    pub capabilities: Vec<String>,
+    pub schema: String,
+    // Names the types behind a schema hash mismatch.
+    pub manifest: Manifest,
+}
+
+impl Hello {
+    pub fn new(manifest: Manifest) -> Self {
+        let features = [
+            ("verification", cfg!(feature = "verification")),
+            ("loggers", cfg!(feature = "loggers")),
+        ];
+
+        Self {
+            protocol: PROTOCOL_VERSION,
+            features: features
+                .into_iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(feature, _)| feature.to_string())
+                .collect(),
+            capabilities: Vec::new(),
+            schema: manifest.hash(),
+            manifest,
+        }
+    }
+
+    pub fn with_capability(mut self, capability: &str) -> Self {
+        self.capabilities.push(capability.into());
+        self
+    }
+
+    pub fn to_value(&self) -> JsValue {
+        let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+        self.serialize(&serializer)
+            .expect("Hello should always serialize.")
+    }
+
+    pub fn from_value(value: &JsValue) -> Option<Self> {
+        if !value.is_object() || js_sys::Array::is_array(value) {
+            return None;
+        }
+
+        serde_wasm_bindgen::from_value(value.clone()).ok()
+    }
+
+    // Checked from the side receiving remote, which also needs every one of
+    // requires to be among its capabilities.
+    pub fn check(&self, remote: &Hello, requires: &[&str]) -> Result<(), HandshakeError> {
+        if self.protocol != remote.protocol {
+            return Err(HandshakeError::Protocol {
+                local: self.protocol,
+                remote: remote.protocol,
+            });
+        }
+
+        let wire_features = |hello: &Hello| -> Vec<String> {
+            hello
+                .features
+                .iter()
+                .filter(|feature| WIRE_FEATURES.contains(&feature.as_str()))
+                .cloned()
+                .collect()
+        };
+        let (local, remote_features) = (wire_features(self), wire_features(remote));
+        if local != remote_features {
+            return Err(HandshakeError::Features {
+                local,
+                remote: remote_features,
+            });
+        }
+
+        let missing: Vec<String> = requires
+            .iter()
+            .filter(|capability| !remote.capabilities.iter().any(|c| c == *capability))
+            .map(|capability| capability.to_string())
+            .collect();
+        if !missing.is_empty() {
+            return Err(HandshakeError::Capabilities(missing));
+        }
+
+        if self.schema != remote.schema {
+            let mismatch = self.manifest.compare(&remote.manifest).err();
+            let compatible = self.manifest.compatible_hash();
+            if compatible.is_none() || compatible != remote.manifest.compatible_hash() {
+                return Err(HandshakeError::Schema(mismatch));
+            }
+
+            // Both sides still read what the other writes.
+            match mismatch {
+                Some(mismatch) => warn!("handshake: compatible {}", mismatch),
+                None => warn!("handshake: compatible schema mismatch"),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HandshakeError {
+    Protocol {
+        local: u32,
+        remote: u32,
+    },
+    Features {
+        local: Vec<String>,
+        remote: Vec<String>,
+    },
+    Capabilities(Vec<String>),
+    // None when the manifests agree but were hashed differently.
+    Schema(Option<SchemaMismatch>),
+    // The remote sent something other than a Hello first.
+    Unexpected,
+    Timeout(Duration),
+    // The remote worker failed to load or threw before announcing itself.
+    Worker(String),
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::Protocol { local, remote } => write!(
+                f,
+                "protocol mismatch: local version {}, remote version {}",
+                local, remote
+            ),
+            HandshakeError::Features { local, remote } => write!(
+                f,
+                "feature mismatch: local built with [{}], remote with [{}]",
+                local.join(", "),
+                remote.join(", ")
+            ),
+            HandshakeError::Capabilities(missing) => {
+                write!(f, "remote lacks capabilities: {}", missing.join(", "))
+            }
+            HandshakeError::Schema(Some(mismatch)) => write!(f, "{}", mismatch),
+            HandshakeError::Schema(None) => write!(f, "schema mismatch"),
+            HandshakeError::Unexpected => write!(f, "remote sent a message before hello"),
+            HandshakeError::Timeout(timeout) => {
+                write!(f, "no hello within {}ms", timeout.as_millis())
+            }
+            HandshakeError::Worker(message) => write!(f, "remote failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
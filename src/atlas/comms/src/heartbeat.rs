@@ -0,0 +1,196 @@
+use crate::{
+    port::{Listener, PortError, Shareable, TypedPort},
+    rpc::{self, RpcClient, RpcError},
+};
+use log::warn;
+use std::{
+    cell::Cell,
+    fmt, future,
+    rc::{Rc, Weak},
+    time::Duration,
+};
+
+pub const INTERVAL: Duration = Duration::from_secs(5);
+// Missed beats in a row before the peer is lost.
+pub const TOLERANCE: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    // Missed a beat, but not enough to give up on it.
+    Unresponsive,
+    Lost,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Connected => write!(f, "connected"),
+            ConnectionState::Unresponsive => write!(f, "unresponsive"),
+            ConnectionState::Lost => write!(f, "lost"),
+        }
+    }
+}
+
+// Turns beats and misses into a connection state. A lost peer that beats again
+// is connected again, unless it was given up on.
+#[derive(Debug)]
+pub struct Monitor {
+    tolerance: u32,
+    missed: u32,
+    state: ConnectionState,
+    gone: bool,
+}
+
+impl Monitor {
+    pub fn new(tolerance: u32) -> Self {
+        Self {
+            tolerance,
+            missed: 0,
+            state: ConnectionState::Connected,
+            gone: false,
+        }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    // These return the new state when it changed.
+    pub fn beat(&mut self) -> Option<ConnectionState> {
+        if self.gone {
+            return None;
+        }
+        self.missed = 0;
+        self.set(ConnectionState::Connected)
+    }
+
+    pub fn miss(&mut self) -> Option<ConnectionState> {
+        self.missed = self.missed.saturating_add(1);
+        if self.missed >= self.tolerance {
+            self.set(ConnectionState::Lost)
+        } else {
+            self.set(ConnectionState::Unresponsive)
+        }
+    }
+
+    // For peers that can't come back, like a server that panicked.
+    pub fn give_up(&mut self) -> Option<ConnectionState> {
+        self.gone = true;
+        self.set(ConnectionState::Lost)
+    }
+
+    fn set(&mut self, state: ConnectionState) -> Option<ConnectionState> {
+        (self.state != state).then(|| {
+            self.state = state;
+            state
+        })
+    }
+}
+
+// Pings through the client every interval until it is dropped or the server is
+// gone for good. ping is called once per beat, so it can beat other ports too.
+pub async fn run<Req, Res>(
+    client: Weak<RpcClient<Req, Res>>,
+    ping: impl Fn() -> Req,
+    interval: Duration,
+    on_change: impl Fn(ConnectionState),
+) where
+    Req: Shareable,
+    Res: Shareable + 'static,
+{
+    let mut monitor = Monitor::new(TOLERANCE);
+
+    loop {
+        let Some(strong) = client.upgrade() else {
+            return;
+        };
+        let result = strong
+            .request_with(ping(), Some(interval), future::pending())
+            .await;
+        drop(strong);
+
+        let (change, wait) = match result {
+            Ok(_) => (monitor.beat(), true),
+            // Already waited for a whole interval.
+            Err(RpcError::Timeout(_)) => (monitor.miss(), false),
+            Err(RpcError::Fatal(_)) | Err(RpcError::Port(PortError::Closed)) => {
+                if let Some(state) = monitor.give_up() {
+                    on_change(state);
+                }
+                return;
+            }
+            Err(error) => {
+                warn!("heartbeat: {}", error);
+                (monitor.miss(), true)
+            }
+        };
+
+        if let Some(state) = change {
+            on_change(state);
+        }
+        if wait {
+            rpc::sleep(interval).await;
+        }
+    }
+}
+
+// The last beat of a peer and the interval it said it beats at, in
+// milliseconds since the epoch like Date.now().
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Beats {
+    last: Option<(f64, Duration)>,
+}
+
+impl Beats {
+    pub fn beat(&mut self, at: f64, interval: Duration) {
+        self.last = Some((at, interval));
+    }
+
+    // Peers that never beat don't use heartbeats, so they are never lost.
+    pub fn is_lost(&self, now: f64) -> bool {
+        match self.last {
+            Some((at, interval)) => now - at > (interval * TOLERANCE).as_millis() as f64,
+            None => false,
+        }
+    }
+}
+
+// Listens for the beats of the peer on a port, for the side that doesn't send
+// any. beat picks them out of the other messages, along with their interval.
+pub struct Watch {
+    beats: Rc<Cell<Beats>>,
+    _listener: Listener,
+}
+
+impl Watch {
+    pub fn new<In, Out>(
+        port: &TypedPort<In, Out>,
+        beat: impl Fn(&In) -> Option<Duration> + 'static,
+    ) -> Result<Self, PortError>
+    where
+        In: Shareable,
+        Out: Shareable,
+    {
+        let beats = Rc::new(Cell::new(Beats::default()));
+        let seen = Rc::clone(&beats);
+        let listener =
+            port.add_listener(move |message| match message.as_ref().ok().and_then(&beat) {
+                Some(interval) => {
+                    let mut beats = seen.get();
+                    beats.beat(js_sys::Date::now(), interval);
+                    seen.set(beats);
+                }
+                None => warn!("peer->[watch]: dropped {:?}", message),
+            })?;
+
+        Ok(Self {
+            beats,
+            _listener: listener,
+        })
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.beats.get().is_lost(js_sys::Date::now())
+    }
+}
@@ -1,22 +1,64 @@
-use atlas_comms_derive::Shareable;
-use port::Shareable;
 use std::panic;
 use wasm_bindgen::prelude::*;
 
+pub use atlas_comms_derive::Shareable;
+pub use js_sys;
+pub use port::Shareable;
+#[cfg(feature = "proptest")]
+pub use proptest;
+pub use serde;
+pub use serde_wasm_bindgen;
+pub use wasm_bindgen;
+
+// Lets the derive expand to ::atlas_comms paths inside this crate as well.
+extern crate self as atlas_comms;
+
 pub mod client;
+pub mod handshake;
+pub mod heartbeat;
+pub mod limits;
 pub mod port;
+pub mod rpc;
+pub mod schema;
 pub mod server;
+pub mod testing;
+pub mod typescript;
+pub mod value;
+pub mod wire;
 
 #[derive(Debug, Shareable)]
-pub struct Payload<T>
-where
-    T: Shareable,
-{
+pub struct Payload<T> {
     #[shareable(repr = "serde")]
-    pub id: u8,
+    pub id: rpc::RequestId,
     pub message: T,
 }
 
+// Every type exchanged between client and server, compared on connection. The
+// types nested in the wrappers are listed too, so a mismatch names the one that
+// actually changed.
+pub fn manifest() -> schema::Manifest {
+    schema::Manifest::new()
+        .with::<rpc::Call<client::ClientMessage>>("Call<ClientMessage>")
+        .with::<Payload<client::ClientMessage>>("Payload<ClientMessage>")
+        .with::<client::ClientMessage>("ClientMessage")
+        .with::<rpc::Reply<server::ServerResponse>>("Reply<ServerResponse>")
+        .with::<Payload<server::ServerResponse>>("Payload<ServerResponse>")
+        .with::<rpc::Panic>("Panic")
+        .with::<server::ServerResponse>("ServerResponse")
+        .with::<server::ServerMessage>("ServerMessage")
+        .with::<server::ServerError>("ServerError")
+        .with::<server::ServerEvent>("ServerEvent")
+}
+
+// What the server announces on startup.
+pub fn hello() -> handshake::Hello {
+    rpc::CAPABILITIES
+        .iter()
+        .fold(handshake::Hello::new(manifest()), |hello, capability| {
+            hello.with_capability(capability)
+        })
+}
+
 #[wasm_bindgen(js_name = initOutput)]
 pub fn init_output() {
     panic::set_hook(Box::new(console_error_panic_hook::hook));
@@ -46,12 +88,68 @@ pub fn init_output() {
 mod tests {
     use super::*;
     use atlas_comms_derive::Shareable;
-    use std::fmt::Debug;
+    use std::collections::HashMap;
+    use value::Value;
+    use web_sys::Worker;
+    use wire::ArrayShareable;
+
+    // In a browser the tests share through JsValue, anywhere else through the
+    // native backend so they also run under cargo test.
+    #[cfg(not(target_arch = "wasm32"))]
+    use core::prelude::v1::test as wasm_bindgen_test;
+    #[cfg(target_arch = "wasm32")]
     use wasm_bindgen_test::*;
-    use web_sys::{OffscreenCanvas, Worker};
 
+    #[cfg(target_arch = "wasm32")]
     wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_worker);
 
+    #[cfg(target_arch = "wasm32")]
+    type Data = JsValue;
+    #[cfg(not(target_arch = "wasm32"))]
+    type Data = value::Native;
+
+    trait Share {
+        fn share(self) -> Result<(Data, Option<Data>), port::ShareableError>;
+    }
+
+    impl<M: ArrayShareable> Share for M {
+        fn share(self) -> Result<(Data, Option<Data>), port::ShareableError> {
+            wire::encode_array(self)
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    type Canvas = web_sys::OffscreenCanvas;
+
+    #[cfg(target_arch = "wasm32")]
+    fn canvas() -> Canvas {
+        Canvas::new(0, 0).unwrap()
+    }
+
+    // Stands in for OffscreenCanvas outside of a browser, every one is distinct.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Canvas(usize);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    impl value::Raw for Canvas {
+        fn into_js(self) -> Option<JsValue> {
+            None
+        }
+
+        fn from_js(_: JsValue) -> Option<Self> {
+            None
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn canvas() -> Canvas {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        Canvas(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
     #[derive(Debug, PartialEq, Eq, Shareable)]
     enum PlainEnum {
         Ping,
@@ -59,8 +157,8 @@ mod tests {
 
     #[wasm_bindgen_test]
     fn plain_enum() {
-        let (data, transfer) = PlainEnum::Ping.try_into().unwrap();
-        let recovered: Result<PlainEnum, _> = data.try_into();
+        let (data, transfer) = PlainEnum::Ping.share().unwrap();
+        let recovered: Result<PlainEnum, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -71,30 +169,27 @@ mod tests {
 
     #[wasm_bindgen_test]
     fn invalid_ident() {
-        let payload = js_sys::Array::new();
-        payload.push(&"PlainEnum".into());
-        payload.push(&"invalid".into());
-        let payload: JsValue = payload.into();
+        let payload = Data::array(vec![Data::string("PlainEnum"), Data::string("invalid")]);
 
-        let recovered: Result<PlainEnum, _> = payload.try_into();
+        let recovered: Result<PlainEnum, _> = wire::decode_array(payload);
         assert!(recovered.is_err())
     }
 
     #[derive(Debug, PartialEq, Eq, Shareable)]
     enum TupleEnum {
         Ping,
-        Attach(#[shareable(repr = "raw")] OffscreenCanvas),
+        Attach(#[shareable(repr = "raw")] Canvas),
         Wrap(
             #[shareable(repr = "raw")] Worker,
-            #[shareable(repr = "raw")] OffscreenCanvas,
+            #[shareable(repr = "raw")] Canvas,
         ),
     }
 
     #[wasm_bindgen_test]
     fn value_enum() {
-        let value = OffscreenCanvas::new(0, 0).unwrap();
-        let (data, transfer) = TupleEnum::Attach(value.clone()).try_into().unwrap();
-        let recovered: Result<TupleEnum, _> = data.try_into();
+        let value = canvas();
+        let (data, transfer) = TupleEnum::Attach(value.clone()).share().unwrap();
+        let recovered: Result<TupleEnum, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -107,23 +202,23 @@ mod tests {
     enum StructEnum {
         Wrap {
             #[shareable(repr = "raw")]
-            server: OffscreenCanvas,
+            server: Canvas,
             #[shareable(repr = "raw")]
-            surface: OffscreenCanvas,
+            surface: Canvas,
         },
     }
 
     #[wasm_bindgen_test]
     fn struct_enum() {
-        let value_a = OffscreenCanvas::new(0, 0).unwrap();
-        let value_b = OffscreenCanvas::new(0, 0).unwrap();
+        let value_a = canvas();
+        let value_b = canvas();
         let (data, transfer) = StructEnum::Wrap {
             server: value_a.clone(),
             surface: value_b.clone(),
         }
-        .try_into()
+        .share()
         .unwrap();
-        let recovered: Result<StructEnum, _> = data.try_into();
+        let recovered: Result<StructEnum, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -140,41 +235,41 @@ mod tests {
 
     #[derive(Debug, PartialEq, Eq, Shareable)]
     enum AttrEnum {
-        Draw(#[shareable(repr = "raw", transfer)] OffscreenCanvas),
+        Draw(#[shareable(repr = "raw", transfer)] Canvas),
         Wrap {
             #[shareable(repr = "raw")]
-            server: OffscreenCanvas,
+            server: Canvas,
             #[shareable(repr = "raw", transfer)]
-            surface: OffscreenCanvas,
+            surface: Canvas,
         },
     }
 
     #[wasm_bindgen_test]
     fn attr_enum_tuple() {
-        let value_a = OffscreenCanvas::new(0, 0).unwrap();
-        let (_, transfer) = AttrEnum::Draw(value_a.clone()).try_into().unwrap();
+        let value_a = canvas();
+        let (_, transfer) = AttrEnum::Draw(value_a.clone()).share().unwrap();
 
         assert!(transfer.is_some());
-        let transfer: js_sys::Array = transfer.unwrap().into();
-        let recovered: OffscreenCanvas = transfer.get(0).into();
+        let transfer = transfer.unwrap().into_array().unwrap();
+        let recovered: Canvas = transfer[0].clone().into_raw().unwrap();
         assert_eq!(recovered, value_a);
     }
 
     #[wasm_bindgen_test]
     fn attr_enum_struct() {
-        let value_a = OffscreenCanvas::new(0, 0).unwrap();
-        let value_b = OffscreenCanvas::new(0, 0).unwrap();
+        let value_a = canvas();
+        let value_b = canvas();
 
         let (_, transfer) = AttrEnum::Wrap {
             server: value_a.clone(),
             surface: value_b.clone(),
         }
-        .try_into()
+        .share()
         .unwrap();
 
         assert!(transfer.is_some());
-        let transfer: js_sys::Array = transfer.unwrap().into();
-        let recovered: OffscreenCanvas = transfer.get(0).into();
+        let transfer = transfer.unwrap().into_array().unwrap();
+        let recovered: Canvas = transfer[0].clone().into_raw().unwrap();
         assert_eq!(recovered, value_b);
     }
 
@@ -183,8 +278,8 @@ mod tests {
 
     #[wasm_bindgen_test]
     fn plain_struct() {
-        let (data, transfer) = PlainStruct.try_into().unwrap();
-        let recovered: Result<PlainStruct, _> = data.try_into();
+        let (data, transfer) = PlainStruct.share().unwrap();
+        let recovered: Result<PlainStruct, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -194,13 +289,13 @@ mod tests {
     }
 
     #[derive(Debug, PartialEq, Eq, Shareable)]
-    struct TupleStruct(#[shareable(repr = "raw")] OffscreenCanvas);
+    struct TupleStruct(#[shareable(repr = "raw")] Canvas);
 
     #[wasm_bindgen_test]
     fn tuple_struct() {
-        let value = OffscreenCanvas::new(0, 0).unwrap();
-        let (data, transfer) = TupleStruct(value.clone()).try_into().unwrap();
-        let recovered: Result<TupleStruct, _> = data.try_into();
+        let value = canvas();
+        let (data, transfer) = TupleStruct(value.clone()).share().unwrap();
+        let recovered: Result<TupleStruct, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -212,22 +307,22 @@ mod tests {
     #[derive(Debug, PartialEq, Eq, Shareable)]
     struct StructStruct {
         #[shareable(repr = "raw")]
-        worker: OffscreenCanvas,
+        worker: Canvas,
         #[shareable(repr = "raw")]
-        canvas: OffscreenCanvas,
+        canvas: Canvas,
     }
 
     #[wasm_bindgen_test]
     fn struct_struct() {
-        let value_a = OffscreenCanvas::new(0, 0).unwrap();
-        let value_b = OffscreenCanvas::new(0, 0).unwrap();
+        let value_a = canvas();
+        let value_b = canvas();
         let (data, transfer) = StructStruct {
             worker: value_a.clone(),
             canvas: value_b.clone(),
         }
-        .try_into()
+        .share()
         .unwrap();
-        let recovered: Result<StructStruct, _> = data.try_into();
+        let recovered: Result<StructStruct, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -243,42 +338,42 @@ mod tests {
     }
 
     #[derive(Debug, PartialEq, Eq, Shareable)]
-    struct AttrTupleStruct(#[shareable(repr = "raw", transfer)] OffscreenCanvas);
+    struct AttrTupleStruct(#[shareable(repr = "raw", transfer)] Canvas);
 
     #[wasm_bindgen_test]
     fn attr_tuple_struct() {
-        let value_a = OffscreenCanvas::new(0, 0).unwrap();
-        let (_, transfer) = AttrTupleStruct(value_a.clone()).try_into().unwrap();
+        let value_a = canvas();
+        let (_, transfer) = AttrTupleStruct(value_a.clone()).share().unwrap();
 
         assert!(transfer.is_some());
-        let transfer: js_sys::Array = transfer.unwrap().into();
-        let recovered: OffscreenCanvas = transfer.get(0).into();
+        let transfer = transfer.unwrap().into_array().unwrap();
+        let recovered: Canvas = transfer[0].clone().into_raw().unwrap();
         assert_eq!(recovered, value_a);
     }
 
     #[derive(Debug, PartialEq, Eq, Shareable)]
     struct AttrStructStruct {
         #[shareable(repr = "raw")]
-        worker: OffscreenCanvas,
+        worker: Canvas,
         #[shareable(repr = "raw", transfer)]
-        canvas: OffscreenCanvas,
+        canvas: Canvas,
     }
 
     #[wasm_bindgen_test]
     fn attr_struct_struct() {
-        let value_a = OffscreenCanvas::new(0, 0).unwrap();
-        let value_b = OffscreenCanvas::new(0, 0).unwrap();
+        let value_a = canvas();
+        let value_b = canvas();
 
         let (_, transfer) = AttrStructStruct {
             worker: value_a.clone(),
             canvas: value_b.clone(),
         }
-        .try_into()
+        .share()
         .unwrap();
 
         assert!(transfer.is_some());
-        let transfer: js_sys::Array = transfer.unwrap().into();
-        let recovered: OffscreenCanvas = transfer.get(0).into();
+        let transfer = transfer.unwrap().into_array().unwrap();
+        let recovered: Canvas = transfer[0].clone().into_raw().unwrap();
         assert_eq!(recovered, value_b);
     }
 
@@ -294,9 +389,9 @@ mod tests {
     #[wasm_bindgen_test]
     fn serde_tuple_enum() {
         let (data, transfer) = SerdeTupleEnum::Message("voxelstack.me".into(), Some(314))
-            .try_into()
+            .share()
             .unwrap();
-        let recovered: Result<SerdeTupleEnum, _> = data.try_into();
+        let recovered: Result<SerdeTupleEnum, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -325,9 +420,9 @@ mod tests {
             key: "voxelstack.me".into(),
             value: Some(314),
         }
-        .try_into()
+        .share()
         .unwrap();
-        let recovered: Result<SerdeStructEnum, _> = data.try_into();
+        let recovered: Result<SerdeStructEnum, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -346,16 +441,16 @@ mod tests {
     struct SerdeTupleStruct(
         #[shareable(repr = "serde")] String,
         #[shareable(repr = "serde")] Option<u32>,
-        #[shareable(repr = "raw")] OffscreenCanvas,
+        #[shareable(repr = "raw")] Canvas,
     );
 
     #[wasm_bindgen_test]
     fn serde_tuple_struct() {
-        let value_a = OffscreenCanvas::new(0, 0).unwrap();
+        let value_a = canvas();
         let (data, transfer) = SerdeTupleStruct("voxelstack.me".into(), Some(314), value_a.clone())
-            .try_into()
+            .share()
             .unwrap();
-        let recovered: Result<SerdeTupleStruct, _> = data.try_into();
+        let recovered: Result<SerdeTupleStruct, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -381,9 +476,9 @@ mod tests {
             key: "voxelstack.me".into(),
             value: Some(314),
         }
-        .try_into()
+        .share()
         .unwrap();
-        let recovered: Result<SerdeStructStruct, _> = data.try_into();
+        let recovered: Result<SerdeStructStruct, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -399,23 +494,20 @@ mod tests {
     }
 
     #[derive(Debug, PartialEq, Eq, Shareable)]
-    struct Generic<T>
-    where
-        T: Into<JsValue> + From<JsValue> + Debug,
-    {
+    struct Generic<T> {
         #[shareable(repr = "raw")]
         value: T,
     }
 
     #[wasm_bindgen_test]
     fn generic() {
-        let value = OffscreenCanvas::new(0, 0).unwrap();
+        let value = canvas();
         let (data, transfer) = Generic {
             value: value.clone(),
         }
-        .try_into()
+        .share()
         .unwrap();
-        let recovered: Result<Generic<OffscreenCanvas>, _> = data.try_into();
+        let recovered: Result<Generic<Canvas>, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -424,13 +516,13 @@ mod tests {
         assert_eq!(transfer, None);
     }
 
-    #[derive(Debug, PartialEq, Eq, Shareable)]
+    #[derive(Debug, Clone, PartialEq, Eq, Shareable)]
     struct Child {
         #[shareable(repr = "serde")]
         id: String,
     }
 
-    #[derive(Debug, PartialEq, Eq, Shareable)]
+    #[derive(Debug, Clone, PartialEq, Eq, Shareable)]
     enum Parent {
         Ping,
         Attach(Child),
@@ -441,9 +533,9 @@ mod tests {
         let (data, _) = Parent::Attach(Child {
             id: "surface".into(),
         })
-        .try_into()
+        .share()
         .unwrap();
-        let recovered: Result<Parent, _> = data.try_into();
+        let recovered: Result<Parent, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -456,34 +548,31 @@ mod tests {
         );
     }
 
-    #[derive(Debug, PartialEq, Eq, Shareable)]
+    #[derive(Debug, Clone, PartialEq, Eq, Shareable)]
     struct ChildTransfer {
         #[shareable(repr = "serde")]
         id: String,
-        #[shareable(repr = "raw", transfer)]
-        canvas: OffscreenCanvas,
+        #[shareable(repr = "raw", transfer, strategy = "arbitrary_canvas")]
+        canvas: Canvas,
     }
 
     #[derive(Debug, PartialEq, Eq, Shareable)]
     enum ParentTransfer {
         Ping,
         Attach(ChildTransfer),
-        Transfer(
-            ChildTransfer,
-            #[shareable(repr = "raw", transfer)] OffscreenCanvas,
-        ),
+        Transfer(ChildTransfer, #[shareable(repr = "raw", transfer)] Canvas),
     }
 
     #[wasm_bindgen_test]
     fn nested_transfer() {
-        let value = OffscreenCanvas::new(0, 0).unwrap();
+        let value = canvas();
         let (data, transfer) = ParentTransfer::Attach(ChildTransfer {
             id: "surface".into(),
             canvas: value.clone(),
         })
-        .try_into()
+        .share()
         .unwrap();
-        let recovered: Result<ParentTransfer, _> = data.try_into();
+        let recovered: Result<ParentTransfer, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -496,15 +585,15 @@ mod tests {
             })
         );
         assert!(transfer.is_some());
-        let transfer: js_sys::Array = transfer.unwrap().into();
-        let recovered: OffscreenCanvas = transfer.get(0).into();
+        let transfer = transfer.unwrap().into_array().unwrap();
+        let recovered: Canvas = transfer[0].clone().into_raw().unwrap();
         assert_eq!(recovered, value);
     }
 
     #[wasm_bindgen_test]
     fn nested_transfer_multiple() {
-        let value_a = OffscreenCanvas::new(0, 0).unwrap();
-        let value_b = OffscreenCanvas::new(0, 0).unwrap();
+        let value_a = canvas();
+        let value_b = canvas();
         let (data, transfer) = ParentTransfer::Transfer(
             ChildTransfer {
                 id: "surface".into(),
@@ -512,9 +601,9 @@ mod tests {
             },
             value_b.clone(),
         )
-        .try_into()
+        .share()
         .unwrap();
-        let recovered: Result<ParentTransfer, _> = data.try_into();
+        let recovered: Result<ParentTransfer, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -530,18 +619,15 @@ mod tests {
             )
         );
         assert!(transfer.is_some());
-        let transfer: js_sys::Array = transfer.unwrap().into();
-        let recovered: OffscreenCanvas = transfer.get(0).into();
+        let transfer = transfer.unwrap().into_array().unwrap();
+        let recovered: Canvas = transfer[0].clone().into_raw().unwrap();
         assert_eq!(recovered, value_a);
-        let recovered: OffscreenCanvas = transfer.get(1).into();
+        let recovered: Canvas = transfer[1].clone().into_raw().unwrap();
         assert_eq!(recovered, value_b);
     }
 
     #[derive(Debug, PartialEq, Eq, Shareable)]
-    struct ParentGeneric<T>
-    where
-        T: Shareable,
-    {
+    struct ParentGeneric<T> {
         value: T,
     }
 
@@ -552,9 +638,9 @@ mod tests {
                 id: "surface".into(),
             },
         }
-        .try_into()
+        .share()
         .unwrap();
-        let recovered: Result<ParentGeneric<Child>, _> = data.try_into();
+        let recovered: Result<ParentGeneric<Child>, _> = wire::decode_array(data);
 
         assert!(recovered.is_ok());
         let recovered = recovered.unwrap();
@@ -570,13 +656,1564 @@ mod tests {
         assert_eq!(transfer, None);
     }
 
+    #[derive(Debug, PartialEq, Eq, Shareable)]
+    struct SerdeGeneric<T> {
+        #[shareable(repr = "serde")]
+        value: T,
+        #[shareable(skip)]
+        cached: Option<T>,
+    }
+
+    #[derive(Debug, PartialEq, Eq, Shareable)]
+    #[shareable(bound = "T: Shareable + Eq")]
+    struct BoundGeneric<T> {
+        values: Vec<Box<T>>,
+    }
+
     #[wasm_bindgen_test]
-    #[cfg(feature = "verification")]
-    fn incompatible_type() {
-        let (data, _) = PlainEnum::Ping.try_into().unwrap();
-        let recovered: Result<PlainStruct, _> = data.try_into();
+    fn generic_bounds() {
+        let (data, _) = SerdeGeneric {
+            value: vec![1u32, 2, 3],
+            cached: Some(vec![1]),
+        }
+        .share()
+        .unwrap();
+        let recovered: Result<SerdeGeneric<Vec<u32>>, _> = wire::decode_array(data);
+
+        assert_eq!(
+            recovered.unwrap(),
+            SerdeGeneric {
+                value: vec![1, 2, 3],
+                cached: None,
+            }
+        );
+
+        let (data, _) = BoundGeneric {
+            values: vec![Box::new(Child { id: "a".into() })],
+        }
+        .share()
+        .unwrap();
+        let recovered: Result<BoundGeneric<Child>, _> = wire::decode_array(data);
+
+        assert_eq!(
+            recovered.unwrap(),
+            BoundGeneric {
+                values: vec![Box::new(Child { id: "a".into() })],
+            }
+        );
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Shareable)]
+    struct Containers {
+        child: Option<ChildTransfer>,
+        children: Vec<ChildTransfer>,
+        named: HashMap<String, Child>,
+        #[shareable(repr = "raw", transfer, strategy = "arbitrary_canvas")]
+        canvases: Vec<Option<Canvas>>,
+    }
+
+    #[wasm_bindgen_test]
+    fn containers() {
+        let value_a = canvas();
+        let value_b = canvas();
+        let value_c = canvas();
+        let containers = || Containers {
+            child: Some(ChildTransfer {
+                id: "surface".into(),
+                canvas: value_a.clone(),
+            }),
+            children: vec![ChildTransfer {
+                id: "minimap".into(),
+                canvas: value_b.clone(),
+            }],
+            named: HashMap::from([(
+                "child".into(),
+                Child {
+                    id: "surface".into(),
+                },
+            )]),
+            canvases: vec![None, Some(value_c.clone())],
+        };
+
+        let (data, transfer) = containers().share().unwrap();
+        let recovered: Result<Containers, _> = wire::decode_array(data);
+
+        assert!(recovered.is_ok());
+        let recovered = recovered.unwrap();
+
+        assert_eq!(recovered, containers());
+        assert!(transfer.is_some());
+        let transfer = transfer.unwrap().into_array().unwrap();
+        assert_eq!(transfer.len(), 3);
+        let recovered: Canvas = transfer[0].clone().into_raw().unwrap();
+        assert_eq!(recovered, value_a);
+        let recovered: Canvas = transfer[1].clone().into_raw().unwrap();
+        assert_eq!(recovered, value_b);
+        let recovered: Canvas = transfer[2].clone().into_raw().unwrap();
+        assert_eq!(recovered, value_c);
+    }
+
+    #[wasm_bindgen_test]
+    fn empty_containers() {
+        let (data, transfer) = Containers {
+            child: None,
+            children: Vec::new(),
+            named: HashMap::new(),
+            canvases: Vec::new(),
+        }
+        .share()
+        .unwrap();
+        let recovered: Result<Containers, _> = wire::decode_array(data);
+
+        assert!(recovered.is_ok());
+        let recovered = recovered.unwrap();
+
+        assert_eq!(
+            recovered,
+            Containers {
+                child: None,
+                children: Vec::new(),
+                named: HashMap::new(),
+                canvases: Vec::new(),
+            }
+        );
+        assert_eq!(transfer, None);
+    }
+
+    #[derive(Debug, Clone, PartialEq, Shareable)]
+    struct SerdeCompound(
+        #[shareable(repr = "serde")] (u32, u32),
+        #[shareable(repr = "serde")] [f32; 4],
+    );
+
+    #[wasm_bindgen_test]
+    fn serde_compound() {
+        let (data, transfer) = SerdeCompound((1920, 1080), [0.0, 0.25, 0.5, 1.0])
+            .share()
+            .unwrap();
+        let recovered: Result<SerdeCompound, _> = wire::decode_array(data);
+
+        assert!(recovered.is_ok());
+        let recovered = recovered.unwrap();
+
+        assert_eq!(
+            recovered,
+            SerdeCompound((1920, 1080), [0.0, 0.25, 0.5, 1.0])
+        );
+        assert_eq!(transfer, None);
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Shareable)]
+    enum Compound {
+        Pair((Child, Box<Child>)),
+        Canvases(#[shareable(repr = "raw", transfer, strategy = "arbitrary_canvas")] [Canvas; 2]),
+    }
+
+    #[wasm_bindgen_test]
+    fn compound_tuple() {
+        let pair = || {
+            Compound::Pair((
+                Child {
+                    id: "surface".into(),
+                },
+                Box::new(Child {
+                    id: "minimap".into(),
+                }),
+            ))
+        };
+        let (data, transfer) = pair().share().unwrap();
+        let recovered: Result<Compound, _> = wire::decode_array(data);
+
+        assert!(recovered.is_ok());
+        let recovered = recovered.unwrap();
+
+        assert_eq!(recovered, pair());
+        assert_eq!(transfer, None);
+    }
+
+    #[wasm_bindgen_test]
+    fn compound_array() {
+        let value_a = canvas();
+        let value_b = canvas();
+        let (data, transfer) = Compound::Canvases([value_a.clone(), value_b.clone()])
+            .share()
+            .unwrap();
+        let recovered: Result<Compound, _> = wire::decode_array(data);
+
+        assert!(recovered.is_ok());
+        let recovered = recovered.unwrap();
+
+        assert_eq!(
+            recovered,
+            Compound::Canvases([value_a.clone(), value_b.clone()])
+        );
+        assert!(transfer.is_some());
+        let transfer = transfer.unwrap().into_array().unwrap();
+        let recovered: Canvas = transfer[0].clone().into_raw().unwrap();
+        assert_eq!(recovered, value_a);
+        let recovered: Canvas = transfer[1].clone().into_raw().unwrap();
+        assert_eq!(recovered, value_b);
+    }
+
+    #[derive(Debug, Clone, PartialEq, Shareable)]
+    struct Buffers {
+        #[shareable(repr = "buffer", transfer)]
+        vertices: Vec<f32>,
+        #[shareable(repr = "buffer")]
+        pixels: Vec<u8>,
+        #[shareable(repr = "buffer")]
+        indices: Option<Vec<u16>>,
+    }
+
+    #[wasm_bindgen_test]
+    fn buffers() {
+        let buffers = || Buffers {
+            vertices: vec![0.0, 0.5, 1.0],
+            pixels: vec![0, 127, 255],
+            indices: Some(vec![0, 1, 2]),
+        };
+        let (data, transfer) = buffers().share().unwrap();
+        let recovered: Result<Buffers, _> = wire::decode_array(data);
+
+        assert!(recovered.is_ok());
+        let recovered = recovered.unwrap();
+
+        assert_eq!(recovered, buffers());
+        assert!(transfer.is_some());
+        let transfer = transfer.unwrap().into_array().unwrap();
+        assert_eq!(transfer.len(), 1);
+        #[cfg(target_arch = "wasm32")]
+        assert_eq!(
+            js_sys::ArrayBuffer::from(transfer[0].clone()).byte_length(),
+            12
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn invalid_buffer() {
+        let verification = cfg!(feature = "verification");
+        let mut payload = Vec::new();
+        if verification {
+            payload.push(Data::string("Buffers"));
+            payload.push(Data::string("vertices"));
+        }
+        payload.push(Data::buffer(&[0u8; 4][..]));
+        if verification {
+            payload.push(Data::string("pixels"));
+        }
+        payload.push(Data::buffer(&[0u8; 4][..]));
+        if verification {
+            payload.push(Data::string("indices"));
+        }
+        payload.push(Data::undefined());
 
+        let recovered: Result<Buffers, _> = wire::decode_array(Data::array(payload));
         assert!(recovered.is_err());
-        assert_eq!(recovered, Err(port::ShareableError::IncompatibleType));
+
+        let error = recovered.unwrap_err();
+        assert_eq!(error.kind(), port::ShareableErrorKind::BadPayload);
+        assert_eq!(error.path(), "Buffers.vertices");
+        assert_eq!(error.expected(), Some("Float32Array"));
+    }
+
+    #[derive(Debug, Clone, PartialEq, Shareable)]
+    #[shareable(wire = "binary")]
+    enum BinaryEnum {
+        Ping,
+        Draw {
+            #[shareable(repr = "serde")]
+            label: String,
+            #[shareable(repr = "raw", transfer, strategy = "arbitrary_canvas")]
+            canvas: Canvas,
+            children: Vec<Child>,
+            named: HashMap<String, Option<Child>>,
+            #[shareable(repr = "buffer", transfer)]
+            vertices: Vec<f32>,
+        },
+    }
+
+    #[wasm_bindgen_test]
+    fn binary_enum() {
+        let value = canvas();
+        let draw = || BinaryEnum::Draw {
+            label: "voxelstack.me".into(),
+            canvas: value.clone(),
+            children: vec![Child {
+                id: "surface".into(),
+            }],
+            named: HashMap::from([("minimap".into(), None)]),
+            vertices: vec![0.0, 0.5, 1.0],
+        };
+        let (data, transfer) = draw().share().unwrap();
+        let recovered: Result<BinaryEnum, _> = wire::decode_array(data);
+
+        assert!(recovered.is_ok());
+        let recovered = recovered.unwrap();
+
+        assert_eq!(recovered, draw());
+        assert!(transfer.is_some());
+        let transfer = transfer.unwrap().into_array().unwrap();
+        assert_eq!(transfer.len(), 3);
+        let recovered: Canvas = transfer[0].clone().into_raw().unwrap();
+        assert_eq!(recovered, value);
+    }
+
+    #[wasm_bindgen_test]
+    fn binary_wire() {
+        let value_a = canvas();
+        let value_b = canvas();
+        let transfer = || {
+            ParentTransfer::Transfer(
+                ChildTransfer {
+                    id: "surface".into(),
+                    canvas: value_a.clone(),
+                },
+                value_b.clone(),
+            )
+        };
+        let (data, _): (Data, _) = wire::Wire::Binary.encode(transfer()).unwrap();
+        let envelope = data.clone().into_array().unwrap();
+        assert!(envelope[0].clone().into_buffer::<u8>().is_ok());
+
+        let recovered: Result<ParentTransfer, _> = wire::Wire::Binary.decode(data);
+
+        assert!(recovered.is_ok());
+        let recovered = recovered.unwrap();
+
+        assert_eq!(recovered, transfer());
+    }
+
+    #[wasm_bindgen_test]
+    fn binary_trailing_bytes() {
+        let (data, _): (Data, _) = wire::Wire::Binary.encode(PlainEnum::Ping).unwrap();
+        let mut envelope = data.into_array().unwrap();
+        let mut bytes: Vec<u8> = envelope[0].clone().into_buffer().unwrap();
+        bytes.push(0);
+        envelope[0] = Data::buffer(&bytes[..]);
+
+        let envelope = Data::array(envelope);
+        let recovered: Result<PlainEnum, _> = wire::Wire::Binary.decode(envelope.clone());
+        assert_eq!(recovered, Ok(PlainEnum::Ping));
+
+        limits::set(limits::Limits {
+            deny_trailing: true,
+            ..Default::default()
+        });
+        let recovered: Result<PlainEnum, _> = wire::Wire::Binary.decode(envelope);
+        limits::set(limits::Limits::default());
+        assert_eq!(
+            recovered.as_ref().map_err(port::ShareableError::kind),
+            Err(port::ShareableErrorKind::BadPayload)
+        );
+
+        let error = recovered.unwrap_err();
+        assert_eq!(error.path(), "PlainEnum");
+        assert_eq!(error.found(), Some("1 trailing bytes"));
+    }
+
+    #[wasm_bindgen_test]
+    fn binary_overlong_varint() {
+        // The tenth byte spilling past 64 bits, and an eleventh byte.
+        let mut spilled: Vec<u8> = vec![0xff; 9];
+        spilled.push(0x02);
+        let mut long: Vec<u8> = vec![0x80; 10];
+        long.push(0x00);
+
+        for bytes in [spilled, long] {
+            let envelope = Data::array(vec![Data::buffer(&bytes[..]), Data::array(Vec::new())]);
+            let recovered: Result<PlainEnum, _> = wire::Wire::Binary.decode(envelope);
+
+            let error = recovered.unwrap_err();
+            assert_eq!(error.kind(), port::ShareableErrorKind::BadPayload);
+            assert_eq!(error.found(), Some("overlong varint"));
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn error_path() {
+        let (data, _) = Payload {
+            id: 0,
+            message: Parent::Attach(Child {
+                id: "surface".into(),
+            }),
+        }
+        .share()
+        .unwrap();
+
+        // The child id is the last value of the innermost array in every layout.
+        let mut payload = data.into_array().unwrap();
+        let mut message = payload.pop().unwrap().into_array().unwrap();
+        let mut child = message.pop().unwrap().into_array().unwrap();
+        child.pop();
+        child.push(Data::number(314.0));
+        message.push(Data::array(child));
+        payload.push(Data::array(message));
+
+        let recovered: Result<Payload<Parent>, _> = wire::decode_array(Data::array(payload));
+        let error = recovered.unwrap_err();
+
+        assert_eq!(error.kind(), port::ShareableErrorKind::BadPayload);
+        assert_eq!(error.path(), "Payload<Parent>.message.Attach.0.id");
+        assert_eq!(error.expected(), Some("String"));
+        assert!(error.cause().is_some());
+        assert!(error
+            .to_string()
+            .starts_with("invalid payload format at Payload<Parent>.message.Attach.0.id"));
+    }
+
+    #[derive(Debug, PartialEq, Eq, Shareable)]
+    #[shareable(crate = "crate")]
+    struct CratePath {
+        #[shareable(repr = "serde")]
+        id: String,
+    }
+
+    #[wasm_bindgen_test]
+    fn crate_path() {
+        let (data, transfer) = CratePath {
+            id: "surface".into(),
+        }
+        .share()
+        .unwrap();
+        let recovered: Result<CratePath, _> = wire::decode_array(data);
+
+        assert!(recovered.is_ok());
+        let recovered = recovered.unwrap();
+
+        assert_eq!(
+            recovered,
+            CratePath {
+                id: "surface".into()
+            }
+        );
+        assert_eq!(transfer, None);
+    }
+
+    mod before {
+        use atlas_comms_derive::Shareable;
+
+        #[derive(Debug, Shareable)]
+        pub struct Versioned {
+            #[shareable(repr = "serde")]
+            pub id: u8,
+        }
+
+        #[derive(Debug, Shareable)]
+        pub struct Defaulted {
+            #[shareable(repr = "serde")]
+            pub id: u8,
+            #[shareable(repr = "serde", default)]
+            pub extra: u8,
+        }
+    }
+
+    mod after {
+        use atlas_comms_derive::Shareable;
+
+        #[derive(Debug, Shareable)]
+        pub struct Versioned {
+            #[shareable(repr = "serde")]
+            pub id: u16,
+        }
+
+        #[derive(Debug, Shareable)]
+        pub struct Defaulted {
+            #[shareable(repr = "serde")]
+            pub id: u8,
+            #[shareable(repr = "serde", default)]
+            pub extra: u16,
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Shareable)]
+    enum Tree {
+        Leaf,
+        Node(Box<Tree>, Box<Tree>),
+    }
+
+    #[wasm_bindgen_test]
+    fn fingerprint() {
+        use schema::Fingerprint;
+
+        assert_eq!(
+            before::Versioned::fingerprint(),
+            before::Versioned::fingerprint()
+        );
+        assert_ne!(
+            before::Versioned::fingerprint(),
+            after::Versioned::fingerprint()
+        );
+        // Default fields are still read by position.
+        assert_ne!(
+            before::Defaulted::fingerprint(),
+            after::Defaulted::fingerprint()
+        );
+        assert_eq!(old::Channel::compatible(), new::Channel::compatible());
+        assert_ne!(
+            before::Versioned::compatible(),
+            after::Versioned::compatible()
+        );
+        assert_ne!(
+            ParentGeneric::<Child>::fingerprint(),
+            ParentGeneric::<ChildTransfer>::fingerprint()
+        );
+        assert_ne!(Tree::fingerprint(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn schema_mismatch() {
+        let local = schema::Manifest::new()
+            .with::<Child>("Child")
+            .with::<before::Versioned>("Versioned");
+        let remote = schema::Manifest::new()
+            .with::<Child>("Child")
+            .with::<after::Versioned>("Versioned");
+
+        // Manifests are exchanged as plain JS objects.
+        #[cfg(target_arch = "wasm32")]
+        {
+            let recovered = schema::Manifest::from_value(&remote.to_value());
+            assert_eq!(recovered.as_ref(), Some(&remote));
+
+            let (data, _) = PlainEnum::Ping.try_into().unwrap();
+            assert_eq!(schema::Manifest::from_value(&data), None);
+        }
+        assert_eq!(local.compare(&local), Ok(()));
+
+        let mismatch = local.compare(&remote).unwrap_err();
+        assert_eq!(mismatch.0.len(), 1);
+        assert_eq!(mismatch.0[0].name, "Versioned");
+
+        // Wrappers change with what they wrap, listing both names the cause.
+        let local = local.with::<ParentGeneric<before::Versioned>>("Parent");
+        let remote = remote.with::<ParentGeneric<after::Versioned>>("Parent");
+        let mismatch = local.compare(&remote).unwrap_err();
+        let names: Vec<&str> = mismatch.0.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["Parent", "Versioned"]);
+    }
+
+    #[wasm_bindgen_test]
+    fn handshake() {
+        use handshake::{HandshakeError, Hello};
+
+        let local = Hello::new(schema::Manifest::new().with::<before::Versioned>("Versioned"))
+            .with_capability("cancel");
+        assert_eq!(local.schema, local.manifest.hash());
+
+        // Hellos are exchanged as plain JS objects.
+        #[cfg(target_arch = "wasm32")]
+        {
+            assert_eq!(Hello::from_value(&local.to_value()), Some(local.clone()));
+            let manifest = local.manifest.to_value();
+            assert_eq!(Hello::from_value(&manifest), None);
+        }
+        assert_eq!(local.check(&local, &["cancel"]), Ok(()));
+
+        let mut newer = local.clone();
+        newer.protocol += 1;
+        assert_eq!(
+            local.check(&newer, &[]),
+            Err(HandshakeError::Protocol {
+                local: local.protocol,
+                remote: newer.protocol
+            })
+        );
+
+        // Only features that change the wire have to match.
+        let mut features = local.clone();
+        features.features = vec!["loggers".into()];
+        assert_eq!(
+            local.check(&features, &[]).is_ok(),
+            !cfg!(feature = "verification")
+        );
+        features.features.push("verification".into());
+        assert_eq!(
+            local.check(&features, &[]).is_ok(),
+            cfg!(feature = "verification")
+        );
+
+        assert_eq!(
+            local.check(&local, &["cancel", "fatal"]),
+            Err(HandshakeError::Capabilities(vec!["fatal".into()]))
+        );
+
+        let changed = Hello::new(schema::Manifest::new().with::<after::Versioned>("Versioned"));
+        match local.check(&changed, &[]) {
+            Err(HandshakeError::Schema(Some(mismatch))) => {
+                assert_eq!(mismatch.0[0].name, "Versioned")
+            }
+            result => panic!("expected a schema mismatch, got {:?}", result),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn handshake_evolution() {
+        use handshake::{HandshakeError, Hello};
+
+        let older = Hello::new(
+            schema::Manifest::new()
+                .with::<old::Command>("Command")
+                .with::<old::Channel>("Channel"),
+        );
+        let newer = Hello::new(
+            schema::Manifest::new()
+                .with::<new::Command>("Command")
+                .with::<new::Channel>("Channel"),
+        );
+        assert_ne!(older.schema, newer.schema);
+
+        // New variants on an other enum and a trailing default field only warn.
+        assert_eq!(older.check(&newer, &[]), Ok(()));
+        assert_eq!(newer.check(&older, &[]), Ok(()));
+
+        let older = Hello::new(older.manifest.with::<before::Versioned>("Versioned"));
+        let newer = Hello::new(newer.manifest.with::<after::Versioned>("Versioned"));
+        match older.check(&newer, &[]) {
+            Err(HandshakeError::Schema(Some(mismatch))) => {
+                let names: Vec<&str> = mismatch.0.iter().map(|m| m.name.as_str()).collect();
+                assert_eq!(names, ["Channel", "Command", "Versioned"]);
+            }
+            result => panic!("expected a schema mismatch, got {:?}", result),
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Shareable)]
+    #[shareable(repr = "serde")]
+    enum SerdeContainer {
+        Empty,
+        Surface {
+            label: String,
+            size: (u32, u32),
+            tags: Vec<String>,
+            #[shareable(repr = "raw", transfer, strategy = "arbitrary_canvas")]
+            canvas: Canvas,
+            #[shareable(repr = "shareable")]
+            child: Child,
+        },
+    }
+
+    #[wasm_bindgen_test]
+    fn serde_container() {
+        let canvas = canvas();
+        let surface = || SerdeContainer::Surface {
+            label: "voxelstack.me".into(),
+            size: (640, 480),
+            tags: vec!["main".into()],
+            canvas: canvas.clone(),
+            child: Child {
+                id: "surface".into(),
+            },
+        };
+
+        for wire in [wire::Wire::Array, wire::Wire::Binary] {
+            let (data, transfer): (Data, _) = wire.encode(surface()).unwrap();
+            let recovered: Result<SerdeContainer, _> = wire.decode(data);
+            assert_eq!(recovered, Ok(surface()));
+
+            let transfer = transfer.unwrap().into_array().unwrap();
+            assert!(transfer
+                .into_iter()
+                .any(|item| item.into_raw().ok() == Some(canvas.clone())));
+        }
+
+        let (data, transfer): (Data, _) = wire::Wire::Array.encode(SerdeContainer::Empty).unwrap();
+        let recovered: Result<SerdeContainer, _> = wire::Wire::Array.decode(data);
+        assert_eq!(recovered, Ok(SerdeContainer::Empty));
+        assert_eq!(transfer, None);
+    }
+
+    mod old {
+        use atlas_comms_derive::Shareable;
+
+        #[derive(Debug, PartialEq, Eq, Shareable)]
+        pub struct Settings {
+            #[shareable(repr = "serde")]
+            pub volume: u8,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Eq, Shareable)]
+        pub struct Channel {
+            #[shareable(repr = "serde")]
+            pub volume: u8,
+        }
+
+        #[derive(Debug, PartialEq, Eq, Shareable)]
+        pub enum Command {
+            Ping,
+            Apply(Settings),
+            #[shareable(other)]
+            Unknown,
+        }
+    }
+
+    mod new {
+        use atlas_comms_derive::Shareable;
+
+        #[derive(Debug, PartialEq, Eq, Shareable)]
+        pub struct Settings {
+            #[shareable(repr = "serde", rename = "level", alias = "volume")]
+            pub loudness: u8,
+            #[shareable(repr = "serde", default)]
+            pub muted: bool,
+            #[shareable(skip)]
+            pub cached: Option<String>,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Eq, Shareable)]
+        pub struct Channel {
+            #[shareable(repr = "serde")]
+            pub volume: u8,
+            #[shareable(repr = "serde", default)]
+            pub muted: bool,
+        }
+
+        #[derive(Debug, PartialEq, Eq, Shareable)]
+        pub enum Command {
+            #[shareable(rename = "Ping")]
+            Heartbeat,
+            Apply(Settings),
+            Mute,
+            #[shareable(other)]
+            Unknown,
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn evolution_old_to_new() {
+        let (data, _) = old::Command::Apply(old::Settings { volume: 7 })
+            .share()
+            .unwrap();
+        let recovered: Result<new::Command, _> = wire::decode_array(data);
+
+        assert_eq!(
+            recovered,
+            Ok(new::Command::Apply(new::Settings {
+                loudness: 7,
+                muted: false,
+                cached: None,
+            }))
+        );
+
+        let (data, _) = old::Command::Ping.share().unwrap();
+        let recovered: Result<new::Command, _> = wire::decode_array(data);
+        assert_eq!(recovered, Ok(new::Command::Heartbeat));
+    }
+
+    #[wasm_bindgen_test]
+    fn evolution_new_to_old() {
+        let (data, _) = new::Command::Mute.share().unwrap();
+        let recovered: Result<old::Command, _> = wire::decode_array(data);
+        assert_eq!(recovered, Ok(old::Command::Unknown));
+
+        let (data, _) = new::Command::Heartbeat.share().unwrap();
+        let recovered: Result<old::Command, _> = wire::decode_array(data);
+        assert_eq!(recovered, Ok(old::Command::Ping));
+    }
+
+    #[wasm_bindgen_test]
+    fn evolution_new_to_old_fields() {
+        let channel = new::Channel {
+            volume: 7,
+            muted: true,
+        };
+
+        for wire in [wire::Wire::Array, wire::Wire::Binary] {
+            let (data, _): (Data, _) = wire.encode(channel.clone()).unwrap();
+            let recovered: Result<old::Channel, _> = wire.decode(data);
+            assert_eq!(recovered, Ok(old::Channel { volume: 7 }));
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn skip() {
+        let (data, _) = new::Settings {
+            loudness: 3,
+            muted: true,
+            cached: Some("voxelstack.me".into()),
+        }
+        .share()
+        .unwrap();
+        let recovered: Result<new::Settings, _> = wire::decode_array(data);
+
+        assert_eq!(
+            recovered,
+            Ok(new::Settings {
+                loudness: 3,
+                muted: true,
+                cached: None,
+            })
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn skip_binary() {
+        let (data, _): (Data, _) = wire::Wire::Binary
+            .encode(new::Settings {
+                loudness: 3,
+                muted: true,
+                cached: Some("voxelstack.me".into()),
+            })
+            .unwrap();
+        let recovered: Result<new::Settings, _> = wire::Wire::Binary.decode(data);
+
+        assert_eq!(
+            recovered,
+            Ok(new::Settings {
+                loudness: 3,
+                muted: true,
+                cached: None,
+            })
+        );
+    }
+
+    // Stands in for a foreign type that is neither Raw nor serde.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Color {
+        r: f32,
+        g: f32,
+        b: f32,
+    }
+
+    mod color {
+        use super::*;
+
+        pub fn encode<V: Value>(
+            value: &Color,
+            _transfer: &mut Vec<V>,
+        ) -> Result<V, port::ShareableError> {
+            Ok(V::buffer(&[value.r, value.g, value.b][..]))
+        }
+
+        pub fn decode<V: Value>(value: V) -> Result<Color, port::ShareableError> {
+            let value: Vec<f32> = value.into_buffer().map_err(|value| {
+                port::ShareableError::bad_payload()
+                    .with_expected("Float32Array")
+                    .with_found(value)
+            })?;
+            match value[..] {
+                [r, g, b] => Ok(Color { r, g, b }),
+                _ => Err(port::ShareableError::bad_payload()
+                    .with_expected("3 components")
+                    .with_found(value.len())),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Surface(Canvas);
+
+    mod surface {
+        use super::*;
+
+        pub fn encode<V: Value>(
+            value: &Surface,
+            transfer: &mut Vec<V>,
+        ) -> Result<V, port::ShareableError> {
+            let canvas = V::raw(value.0.clone())?;
+            transfer.push(canvas.clone());
+            Ok(canvas)
+        }
+
+        pub fn decode<V: Value>(value: V) -> Result<Surface, port::ShareableError> {
+            value.into_raw().map(Surface)
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Shareable)]
+    struct With {
+        #[shareable(with = "color", strategy = "arbitrary_color")]
+        color: Color,
+        #[shareable(with = "surface", strategy = "arbitrary_surface")]
+        surface: Surface,
+    }
+
+    #[derive(Debug, PartialEq, Shareable)]
+    #[shareable(wire = "binary")]
+    struct WithBinary(
+        #[shareable(with = "color")] Color,
+        #[shareable(with = "surface")] Surface,
+    );
+
+    #[wasm_bindgen_test]
+    fn with() {
+        let canvas = canvas();
+        let value = || With {
+            color: Color {
+                r: 1.0,
+                g: 0.5,
+                b: 0.0,
+            },
+            surface: Surface(canvas.clone()),
+        };
+        let (data, transfer) = value().share().unwrap();
+        let recovered: Result<With, _> = wire::decode_array(data);
+
+        assert_eq!(recovered, Ok(value()));
+        let transfer = transfer.unwrap().into_array().unwrap();
+        assert_eq!(transfer.len(), 1);
+        assert_eq!(transfer[0].clone().into_raw(), Ok(canvas));
+    }
+
+    #[wasm_bindgen_test]
+    fn with_binary() {
+        let canvas = canvas();
+        let value = || {
+            WithBinary(
+                Color {
+                    r: 1.0,
+                    g: 0.5,
+                    b: 0.0,
+                },
+                Surface(canvas.clone()),
+            )
+        };
+        let (data, transfer) = value().share().unwrap();
+        let recovered: Result<WithBinary, _> = wire::decode_array(data);
+
+        assert_eq!(recovered, Ok(value()));
+        // The canvas and the buffer holding the bytes.
+        let transfer = transfer.unwrap().into_array().unwrap();
+        assert_eq!(transfer.len(), 2);
+        assert_eq!(transfer[0].clone().into_raw(), Ok(canvas));
+    }
+
+    fn non_empty(value: &str) -> Result<(), &'static str> {
+        if value.is_empty() {
+            Err("empty string")
+        } else {
+            Ok(())
+        }
+    }
+
+    fn ordered(range: &Range) -> Result<(), String> {
+        if range.min <= range.max {
+            Ok(())
+        } else {
+            Err(format!("{} > {}", range.min, range.max))
+        }
+    }
+
+    #[derive(Debug, PartialEq, Shareable)]
+    #[shareable(repr = "serde", validate = "ordered")]
+    struct Range {
+        #[shareable(validate = "non_empty")]
+        label: String,
+        min: u8,
+        max: u8,
+    }
+
+    #[derive(Debug, PartialEq, Shareable)]
+    #[shareable(wire = "binary", validate = "ordered_binary")]
+    struct RangeBinary(
+        #[shareable(repr = "serde", validate = "non_empty")] String,
+        Range,
+    );
+
+    fn ordered_binary(range: &RangeBinary) -> Result<(), String> {
+        ordered(&range.1)
+    }
+
+    fn range(label: &str, min: u8, max: u8) -> Range {
+        Range {
+            label: label.into(),
+            min,
+            max,
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn validate() {
+        let (data, _) = range("volume", 0, 10).share().unwrap();
+        let recovered: Result<Range, _> = wire::decode_array(data);
+        assert_eq!(recovered, Ok(range("volume", 0, 10)));
+
+        let (data, _) = range("", 0, 10).share().unwrap();
+        let error = wire::decode_array::<Range>(data).unwrap_err();
+        assert_eq!(error.kind(), port::ShareableErrorKind::InvalidValue);
+        assert_eq!(error.path(), "Range.label");
+        assert_eq!(error.cause(), Some("empty string"));
+
+        let (data, _) = range("volume", 10, 0).share().unwrap();
+        let error = wire::decode_array::<Range>(data).unwrap_err();
+        assert_eq!(error.kind(), port::ShareableErrorKind::InvalidValue);
+        assert_eq!(error.path(), "Range");
+        assert_eq!(error.cause(), Some("10 > 0"));
+    }
+
+    #[wasm_bindgen_test]
+    fn validate_binary() {
+        let (data, _) = RangeBinary("".into(), range("volume", 0, 10))
+            .share()
+            .unwrap();
+        let error = wire::decode_array::<RangeBinary>(data).unwrap_err();
+        assert_eq!(error.kind(), port::ShareableErrorKind::InvalidValue);
+        assert_eq!(error.path(), "RangeBinary.0");
+
+        let (data, _) = RangeBinary("volume".into(), range("volume", 10, 0))
+            .share()
+            .unwrap();
+        let error = wire::decode_array::<RangeBinary>(data).unwrap_err();
+        assert_eq!(error.kind(), port::ShareableErrorKind::InvalidValue);
+        assert_eq!(error.path(), "RangeBinary.1");
+        assert_eq!(error.cause(), Some("10 > 0"));
+    }
+
+    fn tree(depth: usize) -> Tree {
+        (0..depth).fold(Tree::Leaf, |tree, _| {
+            Tree::Node(Box::new(tree), Box::new(Tree::Leaf))
+        })
+    }
+
+    #[wasm_bindgen_test]
+    fn limits_not_an_array() {
+        let error = wire::decode_array::<PlainEnum>(Data::number(314.0)).unwrap_err();
+
+        assert_eq!(error.kind(), port::ShareableErrorKind::BadPayload);
+        assert_eq!(error.expected(), Some("Array"));
+    }
+
+    #[wasm_bindgen_test]
+    fn limits_depth() {
+        limits::set(limits::Limits {
+            max_depth: 4,
+            ..Default::default()
+        });
+
+        for wire in [wire::Wire::Array, wire::Wire::Binary] {
+            let (data, _): (Data, _) = wire.encode(tree(3)).unwrap();
+            let recovered: Result<Tree, _> = wire.decode(data);
+            assert_eq!(recovered, Ok(tree(3)));
+
+            let (data, _): (Data, _) = wire.encode(tree(4)).unwrap();
+            let recovered: Result<Tree, _> = wire.decode(data);
+            assert_eq!(
+                recovered.map_err(|error| error.expected().map(String::from)),
+                Err(Some("at most 4 nested values".into()))
+            );
+        }
+
+        limits::set(limits::Limits::default());
+    }
+
+    #[wasm_bindgen_test]
+    fn limits_length() {
+        limits::set(limits::Limits {
+            max_length: 16,
+            max_string_length: 16,
+            ..Default::default()
+        });
+
+        let containers = |id: &str, canvases| Containers {
+            child: Some(ChildTransfer {
+                id: id.into(),
+                canvas: canvas(),
+            }),
+            children: vec![],
+            named: HashMap::new(),
+            canvases,
+        };
+
+        for wire in [wire::Wire::Array, wire::Wire::Binary] {
+            let (data, _): (Data, _) = wire.encode(containers("surface", vec![None; 17])).unwrap();
+            let error = wire.decode::<Containers>(data).unwrap_err();
+            assert_eq!(error.path(), "Containers.canvases");
+            assert_eq!(error.expected(), Some("at most 16 items"));
+        }
+
+        let (data, _) = containers("surface.voxelstack.me", vec![]).share().unwrap();
+        let error = wire::decode_array::<Containers>(data).unwrap_err();
+        assert_eq!(error.path(), "Containers.child.id");
+        assert_eq!(error.expected(), Some("at most 16 code units"));
+
+        limits::set(limits::Limits::default());
+    }
+
+    #[derive(Debug, Clone, PartialEq, Shareable)]
+    struct Nested(#[shareable(repr = "serde")] Vec<Vec<Vec<u32>>>);
+
+    #[wasm_bindgen_test]
+    fn limits_serde() {
+        limits::set(limits::Limits {
+            max_depth: 4,
+            max_length: 16,
+            ..Default::default()
+        });
+
+        for wire in [wire::Wire::Array, wire::Wire::Binary] {
+            let (data, _): (Data, _) = wire.encode(Nested(vec![vec![vec![]]])).unwrap();
+            let recovered: Result<Nested, _> = wire.decode(data);
+            assert_eq!(recovered, Ok(Nested(vec![vec![vec![]]])));
+
+            // Past the top level, so only bounded while serde decodes.
+            for nested in [vec![vec![vec![1]]], vec![vec![vec![]; 17]]] {
+                let (data, _): (Data, _) = wire.encode(Nested(nested)).unwrap();
+                let error = wire.decode::<Nested>(data).unwrap_err();
+                assert_eq!(error.kind(), port::ShareableErrorKind::BadPayload);
+                assert_eq!(error.path(), "Nested.0");
+            }
+        }
+
+        let (data, _) = Nested(vec![vec![vec![1]]]).share().unwrap();
+        let error = wire::decode_array::<Nested>(data).unwrap_err();
+        assert!(error.cause().unwrap().contains("at most 4 nested values"));
+
+        limits::set(limits::Limits::default());
+    }
+
+    #[wasm_bindgen_test]
+    fn limits_trailing() {
+        let (data, _) = old::Settings { volume: 7 }.share().unwrap();
+        // An unknown field under verification, two trailing values without.
+        let mut payload = data.into_array().unwrap();
+        payload.push(Data::string("muted"));
+        payload.push(Data::number(1.0));
+        let data = Data::array(payload);
+
+        let recovered: Result<old::Settings, _> = wire::decode_array(data.clone());
+        assert_eq!(recovered, Ok(old::Settings { volume: 7 }));
+
+        limits::set(limits::Limits {
+            deny_trailing: true,
+            ..Default::default()
+        });
+
+        let recovered: Result<old::Settings, _> = wire::decode_array(data);
+        let error = recovered.unwrap_err();
+        assert_eq!(error.kind(), port::ShareableErrorKind::BadPayload);
+        assert_eq!(error.path(), "Settings");
+
+        limits::set(limits::Limits::default());
+    }
+
+    #[wasm_bindgen_test]
+    fn typescript() {
+        use typescript::TypeScript;
+
+        // Raw types are named after the class behind the alias.
+        let class = if cfg!(target_arch = "wasm32") {
+            "OffscreenCanvas"
+        } else {
+            "Canvas"
+        };
+        assert_eq!(
+            Containers::declaration(),
+            format!(
+                "export interface Containers {{\n\
+                 \tchild: ChildTransfer | undefined;\n\
+                 \tchildren: ChildTransfer[];\n\
+                 \tnamed: Map<string, Child>;\n\
+                 \tcanvases: ({} | undefined)[];\n\
+                 }}\n",
+                class
+            )
+        );
+        assert_eq!(
+            Buffers::declaration(),
+            "export interface Buffers {\n\
+             \tvertices: Float32Array;\n\
+             \tpixels: Uint8Array;\n\
+             \tindices: Uint16Array | undefined;\n\
+             }\n"
+        );
+        assert_eq!(
+            Parent::declaration(),
+            "export interface ParentMap {\n\
+             \t'Parent::Ping': undefined;\n\
+             \t'Parent::Attach': Child;\n\
+             }\n\
+             export type ParentTag = keyof ParentMap;\n\
+             export type Parent = {\n\
+             \t[K in ParentTag]: [K, ParentMap[K]];\n\
+             }[ParentTag];\n"
+        );
+        assert!(
+            ParentGeneric::<Child>::declaration().starts_with("export interface ParentGeneric<T>")
+        );
+    }
+
+    // JsValue compares arrays by reference.
+    #[wasm_bindgen_test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn into_ts() {
+        use typescript::TypeScript;
+
+        let value = Parent::Attach(Child {
+            id: "surface".into(),
+        })
+        .into_ts();
+        assert_eq!(
+            value,
+            Ok(Data::array(vec![
+                Data::string("Parent::Attach"),
+                Data::object(vec![("id", Data::string("surface"))]),
+            ]))
+        );
+
+        let value = server::ServerEvent::Count(3).into_ts();
+        assert_eq!(
+            value,
+            Ok(Data::array(vec![
+                Data::string("ServerEvent::Count"),
+                Data::number(3.0),
+            ]))
+        );
+    }
+
+    #[wasm_bindgen_test]
+    #[cfg(feature = "verification")]
+    fn incompatible_type() {
+        let (data, _) = PlainEnum::Ping.share().unwrap();
+        let recovered: Result<PlainStruct, _> = wire::decode_array(data);
+
+        assert!(recovered.is_err());
+        assert_eq!(
+            recovered.map_err(|error| error.kind()),
+            Err(port::ShareableErrorKind::IncompatibleType)
+        );
+    }
+
+    #[cfg(feature = "verification")]
+    mod positional {
+        pub mod before {
+            use atlas_comms_derive::Shareable;
+
+            #[derive(Debug, PartialEq, Eq, Shareable)]
+            pub struct Pair(
+                #[shareable(repr = "serde")] pub u32,
+                #[shareable(repr = "serde")] pub String,
+            );
+        }
+
+        pub mod swapped {
+            use atlas_comms_derive::Shareable;
+
+            #[derive(Debug, PartialEq, Eq, Shareable)]
+            pub struct Pair(
+                #[shareable(repr = "serde")] pub String,
+                #[shareable(repr = "serde")] pub u32,
+            );
+        }
+
+        pub mod extended {
+            use atlas_comms_derive::Shareable;
+
+            #[derive(Debug, PartialEq, Eq, Shareable)]
+            pub struct Pair(
+                #[shareable(repr = "serde")] pub u32,
+                #[shareable(repr = "serde")] pub String,
+                #[shareable(repr = "serde", default)] pub u8,
+            );
+        }
+    }
+
+    #[wasm_bindgen_test]
+    #[cfg(feature = "verification")]
+    fn positional_verification() {
+        let (data, _) = positional::before::Pair(1, "one".into()).share().unwrap();
+        let recovered: Result<positional::swapped::Pair, _> = wire::decode_array(data.clone());
+
+        let error = recovered.unwrap_err();
+        assert_eq!(error.kind(), port::ShareableErrorKind::IncompatibleType);
+        assert_eq!(error.path(), "Pair.0");
+
+        // Missing default fields are still read as their default.
+        let recovered: Result<positional::extended::Pair, _> = wire::decode_array(data);
+        assert_eq!(
+            recovered.unwrap(),
+            positional::extended::Pair(1, "one".into(), 0)
+        );
+
+        let (data, _) = positional::extended::Pair(1, "one".into(), 2)
+            .share()
+            .unwrap();
+        let recovered: Result<positional::before::Pair, _> = wire::decode_array(data);
+
+        let error = recovered.unwrap_err();
+        assert_eq!(error.kind(), port::ShareableErrorKind::IncompatibleType);
+        assert_eq!(error.expected(), Some("2 fields"));
+        assert_eq!(error.found(), Some("3 fields"));
+    }
+
+    #[wasm_bindgen_test]
+    #[cfg(target_arch = "wasm32")]
+    fn send_errors() {
+        let channel = web_sys::MessageChannel::new().unwrap();
+        let port = port::Port::wrap(Box::new(channel.port1()));
+
+        let canvas = canvas();
+        let message = |id: &str| ChildTransfer {
+            id: id.into(),
+            canvas: canvas.clone(),
+        };
+        assert_eq!(port.send(message("a")), Ok(()));
+
+        // The canvas was detached by the first send.
+        let error = port.send(message("b")).unwrap_err();
+        assert!(matches!(
+            error,
+            port::PortError::Rejected { ref name, .. } if name == "DataCloneError"
+        ));
+
+        port.close();
+        assert!(port.is_closed());
+        assert_eq!(port.send(PlainStruct), Err(port::PortError::Closed));
+        assert_eq!(port.post(JsValue::NULL), Err(port::PortError::Closed));
+    }
+
+    #[wasm_bindgen_test]
+    #[cfg(target_arch = "wasm32")]
+    async fn typed_port() {
+        let channel = web_sys::MessageChannel::new().unwrap();
+        let client: port::TypedPort<Parent, Child> =
+            port::TypedPort::new(port::Port::wrap(Box::new(channel.port1())));
+        let server: port::TypedPort<Child, Parent> =
+            port::TypedPort::new(port::Port::wrap(Box::new(channel.port2())));
+
+        let mut messages = server.messages().unwrap();
+        client.send(Child { id: "a".into() }).unwrap();
+        client.port().post(JsValue::from("not a child")).unwrap();
+        client.send(Child { id: "b".into() }).unwrap();
+
+        assert_eq!(messages.recv().await, Some(Ok(Child { id: "a".into() })));
+        // Bad messages don't end the stream.
+        assert!(matches!(messages.recv().await, Some(Err(_))));
+        assert_eq!(messages.recv().await, Some(Ok(Child { id: "b".into() })));
+    }
+
+    #[wasm_bindgen_test]
+    #[cfg(target_arch = "wasm32")]
+    async fn listener_outlives_port() {
+        let channel = web_sys::MessageChannel::new().unwrap();
+        let client: port::TypedPort<Parent, Child> =
+            port::TypedPort::new(port::Port::wrap(Box::new(channel.port1())));
+        let server: port::TypedPort<Child, Parent> =
+            port::TypedPort::new(port::Port::wrap(Box::new(channel.port2())));
+
+        let mut messages = server.messages().unwrap();
+        drop(server);
+        client.send(Child { id: "a".into() }).unwrap();
+
+        assert_eq!(messages.recv().await, Some(Ok(Child { id: "a".into() })));
+    }
+
+    #[wasm_bindgen_test]
+    #[cfg(target_arch = "wasm32")]
+    async fn rpc_routes_by_id() {
+        use std::rc::Rc;
+
+        let channel = web_sys::MessageChannel::new().unwrap();
+        let client: Rc<rpc::RpcClient<Child, Child>> =
+            Rc::new(rpc::RpcClient::new(port::Port::wrap(Box::new(channel.port1()))).unwrap());
+        let server: rpc::RpcServer<Child, Child> =
+            rpc::RpcServer::new(port::Port::wrap(Box::new(channel.port2())));
+
+        // Answers out of order, after a response nobody asked for.
+        wasm_bindgen_futures::spawn_local(async move {
+            let mut requests = server.requests().unwrap();
+            let first = requests.recv().await.unwrap().unwrap();
+            let second = requests.recv().await.unwrap().unwrap();
+
+            let stray = first.id.max(second.id) + 1;
+            server.respond(stray, Child { id: "stray".into() }).unwrap();
+            server.respond(second.id, second.message).unwrap();
+            server.respond(first.id, first.message).unwrap();
+        });
+
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let pending = Rc::clone(&client);
+        wasm_bindgen_futures::spawn_local(async move {
+            let response = pending.request(Child { id: "a".into() }).await;
+            sender.send(response).unwrap();
+        });
+
+        assert_eq!(
+            client.request(Child { id: "b".into() }).await,
+            Ok(Child { id: "b".into() })
+        );
+        assert_eq!(receiver.await.unwrap(), Ok(Child { id: "a".into() }));
+    }
+
+    #[wasm_bindgen_test]
+    #[cfg(target_arch = "wasm32")]
+    async fn rpc_timeout_cancels() {
+        use std::time::Duration;
+
+        let channel = web_sys::MessageChannel::new().unwrap();
+        let client: rpc::RpcClient<Child, Child> =
+            rpc::RpcClient::new(port::Port::wrap(Box::new(channel.port1()))).unwrap();
+        let server: rpc::RpcServer<Child, Child> =
+            rpc::RpcServer::new(port::Port::wrap(Box::new(channel.port2())));
+
+        // Never answers, but hears about the client giving up.
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        wasm_bindgen_futures::spawn_local(async move {
+            let mut requests = server.requests().unwrap();
+            let request = requests.recv().await.unwrap().unwrap();
+            let work = std::future::pending::<()>();
+
+            assert_eq!(request.cancellation.or_cancelled(work).await, None);
+            assert!(request.cancellation.is_cancelled());
+            server.respond(request.id, request.message).unwrap();
+            sender.send(()).unwrap();
+        });
+
+        let timeout = Duration::from_millis(10);
+        let response = client
+            .request_with(
+                Child { id: "a".into() },
+                Some(timeout),
+                std::future::pending(),
+            )
+            .await;
+        assert_eq!(response, Err(rpc::RpcError::Timeout(timeout)));
+        receiver.await.unwrap();
+
+        let response = client
+            .request_with(Child { id: "b".into() }, None, async {})
+            .await;
+        assert_eq!(response, Err(rpc::RpcError::Cancelled));
+    }
+
+    #[wasm_bindgen_test]
+    #[cfg(target_arch = "wasm32")]
+    async fn rpc_fatal_fails_requests() {
+        let channel = web_sys::MessageChannel::new().unwrap();
+        let client: rpc::RpcClient<Child, Child> =
+            rpc::RpcClient::new(port::Port::wrap(Box::new(channel.port1()))).unwrap();
+        let server: port::TypedPort<rpc::Call<Child>, rpc::Reply<Child>> =
+            port::TypedPort::new(port::Port::wrap(Box::new(channel.port2())));
+
+        let panic = rpc::Panic {
+            message: "boom".into(),
+            location: Some("server.rs:1:1".into()),
+        };
+        let mut calls = server.messages().unwrap();
+        let fatal = panic.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            calls.recv().await.unwrap().unwrap();
+            server.send(rpc::Reply::Fatal(fatal)).unwrap();
+        });
+
+        let response = client.request(Child { id: "a".into() }).await;
+        assert_eq!(response, Err(rpc::RpcError::Fatal(panic.clone())));
+        // Nothing is sent once the server is gone.
+        let response = client.request(Child { id: "b".into() }).await;
+        assert_eq!(response, Err(rpc::RpcError::Fatal(panic)));
+    }
+
+    #[wasm_bindgen_test]
+    fn heartbeat_monitor() {
+        use heartbeat::{ConnectionState::*, Monitor};
+
+        let mut monitor = Monitor::new(2);
+        assert_eq!(monitor.beat(), None);
+        assert_eq!(monitor.miss(), Some(Unresponsive));
+        assert_eq!(monitor.miss(), Some(Lost));
+        assert_eq!(monitor.miss(), None);
+        // Lost peers come back when they beat again.
+        assert_eq!(monitor.beat(), Some(Connected));
+        assert_eq!(monitor.miss(), Some(Unresponsive));
+        assert_eq!(monitor.beat(), Some(Connected));
+
+        assert_eq!(monitor.give_up(), Some(Lost));
+        assert_eq!(monitor.beat(), None);
+        assert_eq!(monitor.state(), Lost);
+    }
+
+    #[wasm_bindgen_test]
+    fn heartbeat_beats() {
+        use heartbeat::{Beats, TOLERANCE};
+        use std::time::Duration;
+
+        let mut beats = Beats::default();
+        assert!(!beats.is_lost(f64::MAX));
+
+        // Slower than the default, but still on time.
+        let interval = Duration::from_secs(20);
+        let timeout = (interval * TOLERANCE).as_millis() as f64;
+        beats.beat(1000.0, interval);
+        assert!(!beats.is_lost(1000.0 + timeout));
+        assert!(beats.is_lost(1000.0 + timeout + 1.0));
+
+        beats.beat(1000.0 + timeout, interval);
+        assert!(!beats.is_lost(1000.0 + timeout + 1.0));
+    }
+
+    #[cfg(feature = "proptest")]
+    fn arbitrary_canvas() -> impl proptest::strategy::Strategy<Value = Canvas> {
+        proptest::strategy::LazyJust::new(canvas)
+    }
+
+    #[cfg(feature = "proptest")]
+    fn arbitrary_color() -> impl proptest::strategy::Strategy<Value = Color> {
+        use proptest::strategy::Strategy;
+
+        (0.0f32..=1.0, 0.0f32..=1.0, 0.0f32..=1.0).prop_map(|(r, g, b)| Color { r, g, b })
+    }
+
+    #[cfg(feature = "proptest")]
+    fn arbitrary_surface() -> impl proptest::strategy::Strategy<Value = Surface> {
+        use proptest::strategy::Strategy;
+
+        arbitrary_canvas().prop_map(Surface)
+    }
+
+    #[cfg(feature = "proptest")]
+    proptest::proptest! {
+        #[wasm_bindgen_test]
+        fn roundtrip_nested(message: Parent) {
+            testing::assert_roundtrip::<Data, _>(message);
+        }
+
+        #[wasm_bindgen_test]
+        fn roundtrip_containers(message: Containers) {
+            testing::assert_roundtrip::<Data, _>(message);
+        }
+
+        #[wasm_bindgen_test]
+        fn roundtrip_compound(message: Compound, serde: SerdeCompound) {
+            testing::assert_roundtrip::<Data, _>(message);
+            testing::assert_roundtrip::<Data, _>(serde);
+        }
+
+        #[wasm_bindgen_test]
+        fn roundtrip_recursive(message: Tree) {
+            testing::assert_roundtrip::<Data, _>(message);
+        }
+
+        #[wasm_bindgen_test]
+        fn roundtrip_buffers(message: Buffers) {
+            testing::assert_roundtrip::<Data, _>(message);
+        }
+
+        #[wasm_bindgen_test]
+        fn roundtrip_binary(message: BinaryEnum) {
+            testing::assert_roundtrip::<Data, _>(message);
+        }
+
+        #[wasm_bindgen_test]
+        fn roundtrip_serde_container(message: SerdeContainer) {
+            testing::assert_roundtrip::<Data, _>(message);
+        }
+
+        #[wasm_bindgen_test]
+        fn roundtrip_with(message: With) {
+            testing::assert_roundtrip::<Data, _>(message);
+        }
+
+        #[wasm_bindgen_test]
+        fn roundtrip_server(response: server::ServerResponse, event: server::ServerEvent) {
+            testing::assert_roundtrip::<Data, _>(response);
+            testing::assert_roundtrip::<Data, _>(event);
+        }
+    }
+
+    // Client messages hold canvases and ports, which only a browser can create.
+    #[cfg(all(feature = "proptest", target_arch = "wasm32"))]
+    proptest::proptest! {
+        #[wasm_bindgen_test]
+        fn roundtrip_client(message: client::ClientMessage) {
+            testing::assert_roundtrip::<Data, _>(message);
+        }
     }
 }
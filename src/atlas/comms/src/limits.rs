@@ -0,0 +1,411 @@
+use crate::{port::ShareableError, value::Value};
+use ::serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use std::{cell::Cell, collections::HashMap, fmt};
+
+// Bounds enforced while decoding, so malformed or hostile payloads are
+// rejected before they can exhaust the stack or memory. They are set per
+// thread, every worker decodes its own messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    // Nested Shareable values, counting the outermost one.
+    pub max_depth: usize,
+    // Items in an array, a map or a payload.
+    pub max_length: usize,
+    // UTF-16 code units on the array wire, bytes on the binary wire.
+    pub max_string_length: usize,
+    // Rejects values left over once every field has been read. Off by default
+    // so older readers accept messages that newer writers extended, readers
+    // that only talk to their own build can turn it on.
+    pub deny_trailing: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_depth: 64,
+            max_length: 1 << 16,
+            max_string_length: 1 << 20,
+            deny_trailing: false,
+        }
+    }
+}
+
+thread_local! {
+    static LIMITS: Cell<Limits> = Cell::new(Limits::default());
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+pub fn set(limits: Limits) {
+    LIMITS.with(|cell| cell.set(limits));
+}
+
+pub fn get() -> Limits {
+    LIMITS.with(Cell::get)
+}
+
+// Held while a Shareable value is being decoded.
+pub struct Depth(());
+
+impl Depth {
+    pub fn enter() -> Result<Self, ShareableError> {
+        let max_depth = get().max_depth;
+        DEPTH.with(|depth| {
+            if depth.get() >= max_depth {
+                return Err(ShareableError::bad_payload()
+                    .with_expected(format_args!("at most {} nested values", max_depth))
+                    .with_found(format_args!("{} or more", max_depth + 1)));
+            }
+
+            depth.set(depth.get() + 1);
+            Ok(Self(()))
+        })
+    }
+}
+
+impl Drop for Depth {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+pub fn length(len: usize, max: usize, unit: &str) -> Result<(), ShareableError> {
+    if len <= max {
+        Ok(())
+    } else {
+        Err(ShareableError::bad_payload()
+            .with_expected(format_args!("at most {} {}", max, unit))
+            .with_found(format_args!("{} {}", len, unit)))
+    }
+}
+
+// Lengths are checked before anything is copied out of the value.
+pub fn array<V: Value>(value: V) -> Result<Vec<V>, ShareableError> {
+    if let Some(len) = value.array_length() {
+        length(len, get().max_length, "items")?;
+    }
+
+    value.into_array().map_err(|value| {
+        ShareableError::bad_payload()
+            .with_expected("Array")
+            .with_found(value)
+    })
+}
+
+pub fn map<V: Value>(value: V) -> Result<Vec<(V, V)>, ShareableError> {
+    if let Some(size) = value.map_size() {
+        length(size, get().max_length, "entries")?;
+    }
+
+    value.into_map().map_err(|value| {
+        ShareableError::bad_payload()
+            .with_expected("Map")
+            .with_found(value)
+    })
+}
+
+pub fn tuple<V: Value>(value: V, len: usize) -> Result<Vec<V>, ShareableError> {
+    let items = array(value)?;
+    if get().deny_trailing && items.len() > len {
+        return Err(ShareableError::bad_payload()
+            .with_expected(format_args!("{} items", len))
+            .with_found(format_args!("{} items", items.len())));
+    }
+
+    Ok(items)
+}
+
+pub fn string<V: Value>(value: V, expected: impl fmt::Display) -> Result<String, ShareableError> {
+    if let Some(len) = value.string_length() {
+        length(len, get().max_string_length, "code units")?;
+    }
+
+    value.as_string().ok_or_else(|| {
+        ShareableError::bad_payload()
+            .with_expected(expected)
+            .with_found(value)
+    })
+}
+
+// Checked before serde copies the value out, what it contains is bounded by
+// Limited as it is decoded.
+pub fn serde<V: Value>(value: &V) -> Result<(), ShareableError> {
+    if let Some(len) = value.string_length() {
+        length(len, get().max_string_length, "code units")
+    } else if let Some(len) = value.array_length() {
+        length(len, get().max_length, "items")
+    } else {
+        Ok(())
+    }
+}
+
+// Takes how many values are left in the payload.
+pub fn trailing(remaining: usize) -> Result<(), ShareableError> {
+    if get().deny_trailing && remaining > 0 {
+        return Err(ShareableError::bad_payload()
+            .with_expected("end of payload")
+            .with_found(format_args!("{} trailing values", remaining)));
+    }
+
+    Ok(())
+}
+
+pub fn unknown_fields<V>(fields: &HashMap<String, V>) -> Result<(), ShareableError> {
+    if get().deny_trailing && !fields.is_empty() {
+        let mut names: Vec<&str> = fields.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        return Err(ShareableError::bad_payload()
+            .with_expected("known fields")
+            .with_found(format_args!("{}", names.join(", "))));
+    }
+
+    Ok(())
+}
+
+// Wraps a serde Deserializer so the values it produces count towards the same
+// depth as Shareable ones, and their strings and collections are held to the
+// same lengths.
+pub struct Limited<D>(pub D);
+
+fn enter<E: de::Error>() -> Result<Depth, E> {
+    Depth::enter().map_err(E::custom)
+}
+
+fn check<E: de::Error>(len: usize, max: usize, unit: &str) -> Result<(), E> {
+    length(len, max, unit).map_err(E::custom)
+}
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*))*) => {$(
+        fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error> {
+            let _depth = enter()?;
+            self.0.$method($($arg,)* Guard(visitor))
+        }
+    )*};
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for Limited<D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any() deserialize_bool() deserialize_char()
+        deserialize_i8() deserialize_i16() deserialize_i32() deserialize_i64() deserialize_i128()
+        deserialize_u8() deserialize_u16() deserialize_u32() deserialize_u64() deserialize_u128()
+        deserialize_f32() deserialize_f64() deserialize_str() deserialize_string()
+        deserialize_bytes() deserialize_byte_buf() deserialize_option() deserialize_unit()
+        deserialize_seq() deserialize_map() deserialize_identifier() deserialize_ignored_any()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_tuple(len: usize)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+struct Guard<V>(V);
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty))*) => {$(
+        fn $method<E: de::Error>(self, value: $ty) -> Result<V::Value, E> {
+            self.0.$method(value)
+        }
+    )*};
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for Guard<V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.0.expecting(formatter)
+    }
+
+    forward_visit! {
+        visit_bool(bool) visit_char(char)
+        visit_i8(i8) visit_i16(i16) visit_i32(i32) visit_i64(i64) visit_i128(i128)
+        visit_u8(u8) visit_u16(u16) visit_u32(u32) visit_u64(u64) visit_u128(u128)
+        visit_f32(f32) visit_f64(f64)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<V::Value, E> {
+        check(value.len(), get().max_string_length, "bytes")?;
+        self.0.visit_str(value)
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, value: &'de str) -> Result<V::Value, E> {
+        check(value.len(), get().max_string_length, "bytes")?;
+        self.0.visit_borrowed_str(value)
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<V::Value, E> {
+        check(value.len(), get().max_string_length, "bytes")?;
+        self.0.visit_string(value)
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<V::Value, E> {
+        check(value.len(), get().max_length, "items")?;
+        self.0.visit_bytes(value)
+    }
+
+    fn visit_borrowed_bytes<E: de::Error>(self, value: &'de [u8]) -> Result<V::Value, E> {
+        check(value.len(), get().max_length, "items")?;
+        self.0.visit_borrowed_bytes(value)
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<V::Value, E> {
+        check(value.len(), get().max_length, "items")?;
+        self.0.visit_byte_buf(value)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+        self.0.visit_none()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+        self.0.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        self.0.visit_some(Limited(deserializer))
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<V::Value, D::Error> {
+        self.0.visit_newtype_struct(Limited(deserializer))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+        if let Some(len) = seq.size_hint() {
+            check(len, get().max_length, "items")?;
+        }
+        self.0.visit_seq(Counted {
+            access: seq,
+            count: 0,
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        if let Some(len) = map.size_hint() {
+            check(len, get().max_length, "entries")?;
+        }
+        self.0.visit_map(Counted {
+            access: map,
+            count: 0,
+        })
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<V::Value, A::Error> {
+        self.0.visit_enum(Limited(data))
+    }
+}
+
+struct Seed<S>(S);
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for Seed<S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<S::Value, D::Error> {
+        self.0.deserialize(Limited(deserializer))
+    }
+}
+
+// Size hints are optional, so items are also counted as they are read.
+struct Counted<A> {
+    access: A,
+    count: usize,
+}
+
+impl<A> Counted<A> {
+    fn count<E: de::Error>(&mut self, unit: &str) -> Result<(), E> {
+        self.count += 1;
+        check(self.count, get().max_length, unit)
+    }
+}
+
+impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for Counted<A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, A::Error> {
+        let element = self.access.next_element_seed(Seed(seed))?;
+        if element.is_some() {
+            self.count("items")?;
+        }
+
+        Ok(element)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.access.size_hint()
+    }
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for Counted<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        let key = self.access.next_key_seed(Seed(seed))?;
+        if key.is_some() {
+            self.count("entries")?;
+        }
+
+        Ok(key)
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value, A::Error> {
+        self.access.next_value_seed(Seed(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.access.size_hint()
+    }
+}
+
+impl<'de, A: EnumAccess<'de>> EnumAccess<'de> for Limited<A> {
+    type Error = A::Error;
+    type Variant = Limited<A::Variant>;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<(S::Value, Self::Variant), A::Error> {
+        let (value, variant) = self.0.variant_seed(Seed(seed))?;
+        Ok((value, Limited(variant)))
+    }
+}
+
+impl<'de, A: VariantAccess<'de>> VariantAccess<'de> for Limited<A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.0.unit_variant()
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, A::Error> {
+        self.0.newtype_variant_seed(Seed(seed))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        self.0.tuple_variant(len, Guard(visitor))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        self.0.struct_variant(fields, Guard(visitor))
+    }
+}
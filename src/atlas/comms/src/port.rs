@@ -1,105 +1,133 @@
-use std::fmt::{self, Debug};
+use std::{
+    cell::Cell,
+    fmt::{self, Debug},
+    future,
+    marker::PhantomData,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
 
+use crate::{
+    schema::Fingerprint,
+    typescript::TypeScript,
+    wire::{ArrayShareable, BinaryShareable, Wire},
+};
+use futures_core::Stream;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
 use wasm_bindgen::prelude::*;
 use web_sys::{DedicatedWorkerGlobalScope, MessageEvent, MessagePort, Worker};
 
 pub trait RawPort {
-    fn send_raw(&self, message: JsValue);
-    fn transfer_raw(&self, message: JsValue, transfer: JsValue);
-    fn add_raw_listener(&self, listener: &js_sys::Function);
-    fn remove_raw_listener(&self, listener: &js_sys::Function);
+    fn send_raw(&self, message: JsValue) -> Result<(), PortError>;
+    fn transfer_raw(&self, message: JsValue, transfer: JsValue) -> Result<(), PortError>;
+    fn add_raw_listener(&self, listener: &js_sys::Function) -> Result<(), PortError>;
+    fn remove_raw_listener(&self, listener: &js_sys::Function) -> Result<(), PortError>;
     fn start(&self) {}
+    fn close(&self) {}
 }
 
 impl RawPort for Worker {
-    fn send_raw(&self, message: JsValue) {
-        self.post_message(&message)
-            .expect("Should have error handling.");
+    fn send_raw(&self, message: JsValue) -> Result<(), PortError> {
+        self.post_message(&message).map_err(PortError::from_js)
     }
 
-    fn transfer_raw(&self, message: JsValue, transfer: JsValue) {
+    fn transfer_raw(&self, message: JsValue, transfer: JsValue) -> Result<(), PortError> {
         self.post_message_with_transfer(&message, &transfer)
-            .expect("Should have error handling.");
+            .map_err(PortError::from_js)
     }
 
-    fn add_raw_listener(&self, listener: &js_sys::Function) {
+    fn add_raw_listener(&self, listener: &js_sys::Function) -> Result<(), PortError> {
         self.add_event_listener_with_callback("message", listener)
-            .expect("Should have error handling.");
+            .map_err(PortError::from_js)
     }
 
-    fn remove_raw_listener(&self, listener: &js_sys::Function) {
+    fn remove_raw_listener(&self, listener: &js_sys::Function) -> Result<(), PortError> {
         self.remove_event_listener_with_callback("message", listener)
-            .expect("Should have error handling.");
+            .map_err(PortError::from_js)
+    }
+
+    fn close(&self) {
+        self.terminate();
     }
 }
 
 impl RawPort for DedicatedWorkerGlobalScope {
-    fn send_raw(&self, message: JsValue) {
-        self.post_message(&message)
-            .expect("Should have error handling.");
+    fn send_raw(&self, message: JsValue) -> Result<(), PortError> {
+        self.post_message(&message).map_err(PortError::from_js)
     }
 
-    fn transfer_raw(&self, message: JsValue, transfer: JsValue) {
+    fn transfer_raw(&self, message: JsValue, transfer: JsValue) -> Result<(), PortError> {
         self.post_message_with_transfer(&message, &transfer)
-            .expect("Should have error handling.");
+            .map_err(PortError::from_js)
     }
 
-    fn add_raw_listener(&self, listener: &js_sys::Function) {
+    fn add_raw_listener(&self, listener: &js_sys::Function) -> Result<(), PortError> {
         self.add_event_listener_with_callback("message", listener)
-            .expect("Should have error handling.");
+            .map_err(PortError::from_js)
     }
 
-    fn remove_raw_listener(&self, listener: &js_sys::Function) {
+    fn remove_raw_listener(&self, listener: &js_sys::Function) -> Result<(), PortError> {
         self.remove_event_listener_with_callback("message", listener)
-            .expect("Should have error handling.");
+            .map_err(PortError::from_js)
+    }
+
+    fn close(&self) {
+        self.close();
     }
 }
 
 impl RawPort for MessagePort {
-    fn send_raw(&self, message: JsValue) {
-        self.post_message(&message)
-            .expect("Should have error handling.");
+    fn send_raw(&self, message: JsValue) -> Result<(), PortError> {
+        self.post_message(&message).map_err(PortError::from_js)
     }
 
-    fn transfer_raw(&self, message: JsValue, transfer: JsValue) {
+    fn transfer_raw(&self, message: JsValue, transfer: JsValue) -> Result<(), PortError> {
         self.post_message_with_transferable(&message, &transfer)
-            .expect("Should have error handling.");
+            .map_err(PortError::from_js)
     }
 
-    fn add_raw_listener(&self, listener: &js_sys::Function) {
+    fn add_raw_listener(&self, listener: &js_sys::Function) -> Result<(), PortError> {
         self.add_event_listener_with_callback("message", listener)
-            .expect("Should have error handling.");
+            .map_err(PortError::from_js)
     }
 
-    fn remove_raw_listener(&self, listener: &js_sys::Function) {
+    fn remove_raw_listener(&self, listener: &js_sys::Function) -> Result<(), PortError> {
         self.remove_event_listener_with_callback("message", listener)
-            .expect("Should have error handling.");
+            .map_err(PortError::from_js)
     }
 
     fn start(&self) {
         self.start();
     }
+
+    fn close(&self) {
+        self.close();
+    }
 }
 
-pub struct Listener<'a> {
-    owner: &'a dyn RawPort,
+// Keeps the port alive and listening until it is cleared or dropped, even if
+// the Port it came from is gone.
+pub struct Listener {
+    owner: Rc<dyn RawPort>,
     inner: Closure<dyn Fn(MessageEvent)>,
 }
 
-impl<'a> Listener<'a> {
-    pub fn clear(self) {
-        self.remove_listener();
+impl Listener {
+    pub fn clear(self) -> Result<(), PortError> {
+        self.remove_listener()
     }
 
-    fn remove_listener(&self) {
+    fn remove_listener(&self) -> Result<(), PortError> {
         self.owner
-            .remove_raw_listener(self.inner.as_ref().unchecked_ref());
+            .remove_raw_listener(self.inner.as_ref().unchecked_ref())
     }
 }
 
-impl<'a> Drop for Listener<'a> {
+impl Drop for Listener {
     fn drop(&mut self) {
-        self.remove_listener();
+        // Nothing to report to, removing an unknown listener is a no-op anyway.
+        let _ = self.remove_listener();
     }
 }
 
@@ -108,61 +136,409 @@ impl<'a> Drop for Listener<'a> {
 pub trait Shareable:
     TryInto<(JsValue, Option<JsValue>), Error = ShareableError>
     + TryFrom<JsValue, Error = ShareableError>
+    + ArrayShareable
+    + BinaryShareable
+    + Fingerprint
+    + TypeScript
     + Debug
 {
 }
 
-#[derive(PartialEq, Eq, Debug, Clone)]
-pub enum ShareableError {
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ShareableErrorKind {
     IncompatibleType,
     BadPayload,
     SerdeFailure,
+    InvalidValue,
+}
+
+// Carries enough context to diagnose a bad message from a single log line:
+// where in the message it failed, what was expected there and what was found.
+// Boxed so the Ok path of every generated Result stays small.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ShareableError(Box<ErrorDetails>);
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+struct ErrorDetails {
+    kind: ShareableErrorKind,
+    ty: Option<String>,
+    path: Vec<String>,
+    expected: Option<String>,
+    found: Option<String>,
+    cause: Option<String>,
+}
+
+impl ShareableError {
+    pub fn new(kind: ShareableErrorKind) -> Self {
+        Self(Box::new(ErrorDetails {
+            kind,
+            ty: None,
+            path: Vec::new(),
+            expected: None,
+            found: None,
+            cause: None,
+        }))
+    }
+
+    pub fn incompatible_type() -> Self {
+        Self::new(ShareableErrorKind::IncompatibleType)
+    }
+
+    pub fn bad_payload() -> Self {
+        Self::new(ShareableErrorKind::BadPayload)
+    }
+
+    pub fn serde_failure() -> Self {
+        Self::new(ShareableErrorKind::SerdeFailure)
+    }
+
+    pub fn invalid_value() -> Self {
+        Self::new(ShareableErrorKind::InvalidValue)
+    }
+
+    pub fn with_expected(mut self, expected: impl fmt::Display) -> Self {
+        self.0.expected = Some(expected.to_string());
+        self
+    }
+
+    pub fn with_found(mut self, found: impl fmt::Debug) -> Self {
+        self.0.found = Some(format!("{:?}", found));
+        self
+    }
+
+    pub fn with_cause(mut self, cause: impl fmt::Display) -> Self {
+        self.0.cause = Some(cause.to_string());
+        self
+    }
+
+    // Errors are built where they happen and gain path segments on the way
+    // out, so segments are prepended.
+    pub fn at(mut self, segment: impl fmt::Display) -> Self {
+        self.0.path.insert(0, segment.to_string());
+        self
+    }
+
+    // Called by every type on the way out, so the outermost one wins.
+    pub fn within<T>(mut self) -> Self
+    where
+        T: ?Sized,
+    {
+        self.0.ty = Some(short_type_name::<T>());
+        self
+    }
+
+    pub fn kind(&self) -> ShareableErrorKind {
+        self.0.kind
+    }
+
+    pub fn path(&self) -> String {
+        let mut path = self.0.ty.clone().unwrap_or_default();
+        for segment in &self.0.path {
+            if !path.is_empty() && !segment.starts_with('[') {
+                path.push('.');
+            }
+            path.push_str(segment);
+        }
+        path
+    }
+
+    pub fn expected(&self) -> Option<&str> {
+        self.0.expected.as_deref()
+    }
+
+    pub fn found(&self) -> Option<&str> {
+        self.0.found.as_deref()
+    }
+
+    pub fn cause(&self) -> Option<&str> {
+        self.0.cause.as_deref()
+    }
 }
 
 impl fmt::Display for ShareableError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ShareableError::IncompatibleType => {
+        match self.0.kind {
+            ShareableErrorKind::IncompatibleType => {
                 write!(
                     f,
                     "the type of the payload doesn't match the type being read"
-                )
+                )?;
             }
-            ShareableError::BadPayload => {
-                write!(f, "invalid payload format")
+            ShareableErrorKind::BadPayload => {
+                write!(f, "invalid payload format")?;
             }
-            ShareableError::SerdeFailure => {
-                write!(f, "serde failed while sharing")
+            ShareableErrorKind::SerdeFailure => {
+                write!(f, "serde failed while sharing")?;
             }
+            ShareableErrorKind::InvalidValue => {
+                write!(f, "the payload was read but failed validation")?;
+            }
+        }
+
+        let path = self.path();
+        if !path.is_empty() {
+            write!(f, " at {}", path)?;
+        }
+        match (&self.0.expected, &self.0.found) {
+            (Some(expected), Some(found)) => write!(f, ": expected {}, found {}", expected, found)?,
+            (Some(expected), None) => write!(f, ": expected {}", expected)?,
+            (None, Some(found)) => write!(f, ": found {}", found)?,
+            (None, None) => {}
         }
+        if let Some(cause) = &self.0.cause {
+            write!(f, " ({})", cause)?;
+        }
+
+        Ok(())
     }
 }
 
-pub struct Port(Box<dyn RawPort>);
+impl std::error::Error for ShareableError {}
+
+// Why a message couldn't be sent. Nothing reaches the other end when sending
+// fails, so the port can keep being used unless it is closed.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum PortError {
+    // The message couldn't be encoded.
+    Encode(ShareableError),
+    // postMessage threw, usually a DataCloneError for a value that can't be
+    // cloned or was already transferred.
+    Rejected { name: String, message: String },
+    // The port was closed on this end.
+    Closed,
+}
+
+impl PortError {
+    fn from_js(error: JsValue) -> Self {
+        let property = |key: &str| {
+            js_sys::Reflect::get(&error, &JsValue::from_str(key))
+                .ok()
+                .and_then(|value| value.as_string())
+                .unwrap_or_default()
+        };
+
+        PortError::Rejected {
+            name: property("name"),
+            message: property("message"),
+        }
+    }
+}
+
+impl From<ShareableError> for PortError {
+    fn from(error: ShareableError) -> Self {
+        PortError::Encode(error)
+    }
+}
+
+impl fmt::Display for PortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortError::Encode(error) => write!(f, "failed to encode the message: {}", error),
+            PortError::Rejected { name, message } => {
+                write!(f, "the port rejected the message: {}: {}", name, message)
+            }
+            PortError::Closed => write!(f, "the port is closed"),
+        }
+    }
+}
+
+impl std::error::Error for PortError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PortError::Encode(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// Drops module paths from std::any::type_name, so errors read
+// Payload<ClientMessage> instead of atlas_comms::Payload<atlas_comms::...>.
+pub(crate) fn short_type_name<T>() -> String
+where
+    T: ?Sized,
+{
+    let full = std::any::type_name::<T>();
+    let mut segments = full.split("::").peekable();
+    let mut short = String::with_capacity(full.len());
+
+    while let Some(segment) = segments.next() {
+        if segments.peek().is_none() {
+            short.push_str(segment);
+        } else {
+            let module_start = segment
+                .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .map(|i| i + 1)
+                .unwrap_or(0);
+            short.push_str(&segment[..module_start]);
+        }
+    }
+
+    short
+}
+
+pub struct Port {
+    raw_port: Rc<dyn RawPort>,
+    wire: Wire,
+    closed: Cell<bool>,
+}
 
 impl Port {
     pub fn wrap(raw_port: Box<dyn RawPort>) -> Self {
+        Self::with_wire(raw_port, Wire::default())
+    }
+
+    pub fn with_wire(raw_port: Box<dyn RawPort>, wire: Wire) -> Self {
         raw_port.start();
-        Self(raw_port)
+        Self {
+            raw_port: raw_port.into(),
+            wire,
+            closed: Cell::new(false),
+        }
+    }
+
+    pub fn wire(&self) -> Wire {
+        self.wire
     }
 
-    pub fn send<M>(&self, message: M)
+    pub fn send<M>(&self, message: M) -> Result<(), PortError>
     where
         M: Shareable,
     {
-        let (data, transfer) = message.try_into().unwrap();
+        self.check_open()?;
+        let (data, transfer) = self.wire.encode(message)?;
         match transfer {
-            Some(transfer) => self.0.transfer_raw(data, transfer),
-            None => self.0.send_raw(data),
+            Some(transfer) => self.raw_port.transfer_raw(data, transfer),
+            None => self.raw_port.send_raw(data),
         }
     }
 
-    pub fn add_listener(&self, listener: Closure<dyn Fn(MessageEvent)>) -> Listener {
-        self.0.add_raw_listener(listener.as_ref().unchecked_ref());
+    // Sends a value as is, for messages that must be readable regardless of the
+    // Shareable layout on the other end.
+    pub fn post(&self, value: JsValue) -> Result<(), PortError> {
+        self.check_open()?;
+        self.raw_port.send_raw(value)
+    }
+
+    pub fn add_listener(
+        &self,
+        listener: Closure<dyn Fn(MessageEvent)>,
+    ) -> Result<Listener, PortError> {
+        self.check_open()?;
+        self.raw_port
+            .add_raw_listener(listener.as_ref().unchecked_ref())?;
 
-        Listener {
-            owner: self.0.as_ref(),
+        Ok(Listener {
+            owner: Rc::clone(&self.raw_port),
             inner: listener,
+        })
+    }
+
+    // Closing is final, everything sent afterwards fails with PortError::Closed.
+    pub fn close(&self) {
+        if !self.closed.replace(true) {
+            self.raw_port.close();
         }
     }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.get()
+    }
+
+    fn check_open(&self) -> Result<(), PortError> {
+        if self.closed.get() {
+            Err(PortError::Closed)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// A port that only sends Out and reads In, decoding with the port's wire.
+pub struct TypedPort<In, Out> {
+    port: Port,
+    messages: PhantomData<fn(Out) -> In>,
+}
+
+impl<In, Out> TypedPort<In, Out>
+where
+    In: Shareable,
+    Out: Shareable,
+{
+    pub fn new(port: Port) -> Self {
+        Self {
+            port,
+            messages: PhantomData,
+        }
+    }
+
+    pub fn send(&self, message: Out) -> Result<(), PortError> {
+        self.port.send(message)
+    }
+
+    // Every stream reads every message that arrives while it exists, and stops
+    // listening once dropped.
+    pub fn messages(&self) -> Result<Messages<In>, PortError> {
+        let (sender, receiver) = unbounded_channel();
+        let listener = self
+            .port
+            .add_listener(Closure::new(move |event: MessageEvent| {
+                // The stream owns the listener, so it can't be gone yet.
+                let _ = sender.send(event.data());
+            }))?;
+
+        Ok(Messages {
+            _listener: listener,
+            receiver,
+            wire: self.port.wire(),
+            messages: PhantomData,
+        })
+    }
+
+    // Handles each message as it arrives, for listeners that don't need to
+    // await anything.
+    pub fn add_listener(
+        &self,
+        listener: impl Fn(Result<In, ShareableError>) + 'static,
+    ) -> Result<Listener, PortError> {
+        let wire = self.port.wire();
+        self.port
+            .add_listener(Closure::new(move |event: MessageEvent| {
+                listener(wire.decode(event.data()))
+            }))
+    }
+
+    // For values sent or read as is, see Port::post.
+    pub fn port(&self) -> &Port {
+        &self.port
+    }
+}
+
+pub struct Messages<In> {
+    _listener: Listener,
+    receiver: UnboundedReceiver<JsValue>,
+    wire: Wire,
+    messages: PhantomData<fn() -> In>,
+}
+
+impl<In> Messages<In>
+where
+    In: Shareable,
+{
+    pub async fn recv(&mut self) -> Option<Result<In, ShareableError>> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl<In> Stream for Messages<In>
+where
+    In: Shareable,
+{
+    type Item = Result<In, ShareableError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let wire = self.wire;
+        self.receiver
+            .poll_recv(cx)
+            .map(|data| data.map(|data| wire.decode(data)))
+    }
 }
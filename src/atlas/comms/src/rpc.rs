@@ -0,0 +1,528 @@
+use crate::{
+    port::{Listener, Port, PortError, Shareable, ShareableError, TypedPort},
+    Payload,
+};
+use atlas_comms_derive::Shareable;
+use futures_core::Stream;
+use log::{error, warn};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt,
+    future::{self, Future},
+    panic,
+    pin::{pin, Pin},
+    rc::Rc,
+    sync::Once,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver},
+    oneshot, watch,
+};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+// Never reused, requests that wait forever would otherwise be answered for
+// whichever one took their id after it wrapped around. They're shared as serde
+// numbers, exact up to 2^53, which no connection gets near.
+pub type RequestId = u64;
+
+// Long enough for any request the server answers, short enough that a hung
+// one is noticed.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Parts of the protocol a peer announces in its handshake.
+pub const CAPABILITIES: &[&str] = &["rpc.cancel", "rpc.fatal"];
+
+type Pending<Res> = Rc<RefCell<HashMap<RequestId, oneshot::Sender<Res>>>>;
+type Cancellations = Rc<RefCell<HashMap<RequestId, watch::Sender<bool>>>>;
+type Immediate<Req, Res> = Rc<RefCell<Option<Box<dyn Fn(&Req) -> Option<Res>>>>>;
+
+// What an RpcClient sends.
+#[derive(Debug, Shareable)]
+pub enum Call<Req> {
+    Request(Payload<Req>),
+    Cancel(#[shareable(repr = "serde")] RequestId),
+}
+
+// What an RpcServer sends, responses carry the id of their request.
+#[derive(Debug, Shareable)]
+pub enum Reply<Res> {
+    Response(Payload<Res>),
+    // The server is gone, nothing else will be answered.
+    Fatal(Panic),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Shareable)]
+#[shareable(repr = "serde")]
+pub struct Panic {
+    pub message: String,
+    pub location: Option<String>,
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{} at {}", self.message, location),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+type ForwardPanic = Box<dyn Fn(Panic)>;
+
+thread_local! {
+    // Set by RpcServer::forward_panics, hooks can't hold a port themselves.
+    static FATAL: RefCell<Option<ForwardPanic>> = RefCell::new(None);
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum RpcError {
+    Port(PortError),
+    Timeout(Duration),
+    Cancelled,
+    Fatal(Panic),
+    // The response can no longer arrive.
+    Disconnected,
+}
+
+impl From<PortError> for RpcError {
+    fn from(error: PortError) -> Self {
+        RpcError::Port(error)
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcError::Port(error) => write!(f, "failed to send the request: {}", error),
+            RpcError::Timeout(timeout) => write!(f, "no response after {:?}", timeout),
+            RpcError::Cancelled => write!(f, "cancelled before the response arrived"),
+            RpcError::Fatal(panic) => write!(f, "the server panicked: {}", panic),
+            RpcError::Disconnected => write!(f, "disconnected before the response arrived"),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RpcError::Port(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// Named like the DOMExceptions fetch rejects with, so JS can tell them apart.
+impl From<RpcError> for JsValue {
+    fn from(error: RpcError) -> Self {
+        let js_error = js_sys::Error::new(&error.to_string());
+        match error {
+            RpcError::Timeout(_) => js_error.set_name("TimeoutError"),
+            RpcError::Cancelled => js_error.set_name("AbortError"),
+            _ => {}
+        }
+        js_error.into()
+    }
+}
+
+// Sends Req and resolves each request with the Res carrying its id. A single
+// listener decodes every response and hands it to the request waiting for it.
+pub struct RpcClient<Req, Res> {
+    port: TypedPort<Reply<Res>, Call<Req>>,
+    next_id: Cell<RequestId>,
+    timeout: Cell<Option<Duration>>,
+    pending: Pending<Res>,
+    fatal: Rc<RefCell<Option<Panic>>>,
+    _dispatcher: Listener,
+}
+
+impl<Req, Res> RpcClient<Req, Res>
+where
+    Req: Shareable,
+    Res: Shareable + 'static,
+{
+    pub fn new(port: Port) -> Result<Self, PortError> {
+        let port = TypedPort::new(port);
+        let pending: Pending<Res> = Rc::default();
+        let fatal: Rc<RefCell<Option<Panic>>> = Rc::default();
+
+        let routes = Rc::clone(&pending);
+        let panicked = Rc::clone(&fatal);
+        let dispatcher = port.add_listener(move |reply| {
+            let Payload { id, message } = match reply {
+                Ok(Reply::Response(payload)) => payload,
+                Ok(Reply::Fatal(panic)) => {
+                    error!("rpc: the server panicked: {}", panic);
+                    *panicked.borrow_mut() = Some(panic);
+                    // Fails every pending request.
+                    routes.borrow_mut().clear();
+                    return;
+                }
+                Err(error) => {
+                    warn!("rpc: dropped a response: {}", error);
+                    return;
+                }
+            };
+
+            match routes.borrow_mut().remove(&id) {
+                // Requests leave the map when dropped, so someone is waiting.
+                Some(sender) => {
+                    let _ = sender.send(message);
+                }
+                // Answered after the request was given up on.
+                None => warn!("rpc: dropped a response to unknown request {}", id),
+            }
+        })?;
+
+        Ok(Self {
+            port,
+            next_id: Cell::new(0),
+            timeout: Cell::new(Some(DEFAULT_TIMEOUT)),
+            pending,
+            fatal,
+            _dispatcher: dispatcher,
+        })
+    }
+
+    // Used by request, None waits forever.
+    pub fn set_timeout(&self, timeout: Option<Duration>) {
+        self.timeout.set(timeout);
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.get()
+    }
+
+    pub async fn request(&self, message: Req) -> Result<Res, RpcError> {
+        self.request_with(message, self.timeout.get(), future::pending())
+            .await
+    }
+
+    // Gives up once the timeout passes or cancel resolves. Then, or if the
+    // request is dropped before the response arrives, the server is told to
+    // stop working on it.
+    pub async fn request_with(
+        &self,
+        message: Req,
+        timeout: Option<Duration>,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<Res, RpcError> {
+        self.check_alive()?;
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1));
+
+        self.port.send(Call::Request(Payload { id, message }))?;
+        // Responses arrive as events, so this can't miss one.
+        let (sender, mut receiver) = oneshot::channel();
+        self.pending.borrow_mut().insert(id, sender);
+        let _pending = PendingRequest { client: self, id };
+
+        let mut timer = pin!(async {
+            match timeout {
+                Some(timeout) => sleep(timeout).await,
+                None => future::pending().await,
+            }
+        });
+        let mut cancel = pin!(cancel);
+
+        future::poll_fn(|cx| {
+            if let Poll::Ready(response) = Pin::new(&mut receiver).poll(cx) {
+                return Poll::Ready(response.map_err(|_| match self.check_alive() {
+                    Ok(()) => RpcError::Disconnected,
+                    Err(error) => error,
+                }));
+            }
+            if cancel.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(RpcError::Cancelled));
+            }
+            match (timer.as_mut().poll(cx), timeout) {
+                (Poll::Ready(()), Some(timeout)) => Poll::Ready(Err(RpcError::Timeout(timeout))),
+                _ => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    // For values sent or read as is, see Port::post.
+    pub fn port(&self) -> &Port {
+        self.port.port()
+    }
+
+    fn check_alive(&self) -> Result<(), RpcError> {
+        match &*self.fatal.borrow() {
+            Some(panic) => Err(RpcError::Fatal(panic.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+struct PendingRequest<'a, Req, Res>
+where
+    Req: Shareable,
+    Res: Shareable,
+{
+    client: &'a RpcClient<Req, Res>,
+    id: RequestId,
+}
+
+impl<'a, Req, Res> Drop for PendingRequest<'a, Req, Res>
+where
+    Req: Shareable,
+    Res: Shareable,
+{
+    fn drop(&mut self) {
+        let abandoned = self.client.pending.borrow_mut().remove(&self.id).is_some();
+        if abandoned {
+            if let Err(error) = self.client.port.send(Call::Cancel(self.id)) {
+                warn!("rpc: failed to cancel request {}: {}", self.id, error);
+            }
+        }
+    }
+}
+
+// The other end of an RpcClient, every request is answered with respond.
+pub struct RpcServer<Req, Res> {
+    port: Rc<TypedPort<Call<Req>, Reply<Res>>>,
+    cancellations: Cancellations,
+    immediate: Immediate<Req, Res>,
+}
+
+impl<Req, Res> RpcServer<Req, Res>
+where
+    Req: Shareable + 'static,
+    Res: Shareable + 'static,
+{
+    pub fn new(port: Port) -> Self {
+        Self {
+            port: Rc::new(TypedPort::new(port)),
+            cancellations: Rc::default(),
+            immediate: Rc::default(),
+        }
+    }
+
+    // Cancellations are read as they arrive, so they reach requests that are
+    // still being worked on.
+    pub fn requests(&self) -> Result<Requests<Req>, PortError> {
+        let (sender, receiver) = unbounded_channel();
+        let cancellations = Rc::clone(&self.cancellations);
+        let immediate = Rc::clone(&self.immediate);
+        let port = Rc::downgrade(&self.port);
+        let listener = self.port.add_listener(move |call| {
+            let request = match call {
+                Ok(Call::Request(Payload { id, message })) => {
+                    let response = immediate
+                        .borrow()
+                        .as_ref()
+                        .and_then(|answer| answer(&message));
+                    if let Some(response) = response {
+                        let reply = Reply::Response(Payload {
+                            id,
+                            message: response,
+                        });
+                        if let Some(Err(error)) = port.upgrade().map(|port| port.send(reply)) {
+                            warn!("rpc: failed to respond to request {}: {}", id, error);
+                        }
+                        return;
+                    }
+
+                    let (cancel, cancelled) = watch::channel(false);
+                    cancellations.borrow_mut().insert(id, cancel);
+                    Ok(Request {
+                        id,
+                        message,
+                        cancellation: Cancellation(cancelled),
+                    })
+                }
+                Ok(Call::Cancel(id)) => {
+                    // Unknown once answered, the client drops the response.
+                    if let Some(cancel) = cancellations.borrow_mut().remove(&id) {
+                        let _ = cancel.send(true);
+                    }
+                    return;
+                }
+                Err(error) => Err(error),
+            };
+            // The stream owns the listener, so it can't be gone yet.
+            let _ = sender.send(request);
+        })?;
+
+        Ok(Requests {
+            _listener: listener,
+            receiver,
+        })
+    }
+
+    // Requests the handler returns a response for are answered as they arrive
+    // and never reach the stream, so they get through while it's busy.
+    pub fn respond_immediately(&self, handler: impl Fn(&Req) -> Option<Res> + 'static) {
+        *self.immediate.borrow_mut() = Some(Box::new(handler));
+    }
+
+    // Cancelled requests aren't answered, nobody is waiting for them.
+    pub fn respond(&self, id: RequestId, message: Res) -> Result<(), PortError> {
+        let cancelled = self.cancellations.borrow_mut().remove(&id).is_none();
+        if cancelled {
+            return Ok(());
+        }
+        self.port.send(Reply::Response(Payload { id, message }))
+    }
+
+    // Panics on this thread are sent to the client before the worker dies,
+    // after running the hook that was already installed.
+    pub fn forward_panics(&self) {
+        let port = Rc::downgrade(&self.port);
+        FATAL.with(|fatal| {
+            *fatal.borrow_mut() = Some(Box::new(move |panic| {
+                if let Some(port) = port.upgrade() {
+                    let _ = port.send(Reply::Fatal(panic));
+                }
+            }));
+        });
+
+        static HOOK: Once = Once::new();
+        HOOK.call_once(|| {
+            let previous = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                previous(info);
+
+                let payload = info.payload();
+                let message = match payload.downcast_ref::<&str>() {
+                    Some(message) => message.to_string(),
+                    None => match payload.downcast_ref::<String>() {
+                        Some(message) => message.clone(),
+                        None => "Box<dyn Any>".to_string(),
+                    },
+                };
+                let panic = Panic {
+                    message,
+                    location: info.location().map(ToString::to_string),
+                };
+
+                let _ = FATAL.try_with(|fatal| {
+                    if let Ok(fatal) = fatal.try_borrow() {
+                        if let Some(forward) = fatal.as_ref() {
+                            forward(panic);
+                        }
+                    }
+                });
+            }));
+        });
+    }
+
+    // For values sent or read as is, see Port::post.
+    pub fn port(&self) -> &Port {
+        self.port.port()
+    }
+}
+
+#[derive(Debug)]
+pub struct Request<Req> {
+    pub id: RequestId,
+    pub message: Req,
+    pub cancellation: Cancellation,
+}
+
+// Whether the client gave up on a request.
+#[derive(Debug, Clone)]
+pub struct Cancellation(watch::Receiver<bool>);
+
+impl Cancellation {
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    // Never resolves for requests that are answered instead.
+    pub async fn cancelled(&self) {
+        let mut cancelled = self.0.clone();
+        loop {
+            if *cancelled.borrow_and_update() {
+                return;
+            }
+            if cancelled.changed().await.is_err() {
+                if *cancelled.borrow() {
+                    return;
+                }
+                future::pending::<()>().await;
+            }
+        }
+    }
+
+    // Stops polling the work once the request is cancelled, None if it was.
+    pub async fn or_cancelled<F: Future>(&self, work: F) -> Option<F::Output> {
+        let mut work = pin!(work);
+        let mut cancelled = pin!(self.cancelled());
+
+        future::poll_fn(|cx| {
+            if let Poll::Ready(output) = work.as_mut().poll(cx) {
+                return Poll::Ready(Some(output));
+            }
+            cancelled.as_mut().poll(cx).map(|()| None)
+        })
+        .await
+    }
+}
+
+pub struct Requests<Req> {
+    _listener: Listener,
+    receiver: UnboundedReceiver<Result<Request<Req>, ShareableError>>,
+}
+
+impl<Req> Requests<Req> {
+    pub async fn recv(&mut self) -> Option<Result<Request<Req>, ShareableError>> {
+        self.receiver.recv().await
+    }
+}
+
+impl<Req> Stream for Requests<Req> {
+    type Item = Result<Request<Req>, ShareableError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+#[wasm_bindgen]
+extern "C" {
+    // Global in both windows and workers.
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &js_sys::Function, timeout: i32) -> i32;
+
+    #[wasm_bindgen(js_name = clearTimeout)]
+    fn clear_timeout(handle: i32);
+}
+
+// Resolves once the duration passes, the timer is cleared if dropped first.
+pub struct Sleep {
+    handle: i32,
+    elapsed: JsFuture,
+}
+
+pub fn sleep(duration: Duration) -> Sleep {
+    let millis = i32::try_from(duration.as_millis()).unwrap_or(i32::MAX);
+    let mut handle = 0;
+    let elapsed = js_sys::Promise::new(&mut |resolve, _| {
+        handle = set_timeout(&resolve, millis);
+    });
+
+    Sleep {
+        handle,
+        elapsed: JsFuture::from(elapsed),
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        Pin::new(&mut self.elapsed).poll(cx).map(|_| ())
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        clear_timeout(self.handle);
+    }
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+use wasm_bindgen::JsValue;
+
+// Implemented by derive(Shareable). The fingerprint covers everything that
+// affects the wire layout: names, field order, reprs and nested fingerprints.
+pub trait Fingerprint {
+    fn fingerprint() -> u64;
+    // Only changes when the two versions can no longer read each other, so it
+    // leaves out the additions older readers skip.
+    fn compatible() -> u64;
+}
+
+// FNV-1a, so fingerprints are stable across builds and platforms.
+pub struct Hasher(u64);
+
+impl Hasher {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    pub fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write_bytes(value.as_bytes());
+        // Terminate so ("ab", "c") and ("a", "bc") hash differently.
+        self.write_bytes(&[0xff]);
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The manifest is exchanged as a plain object instead of a Shareable so it can
+// still be read when the two sides disagree on the layout of everything else.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    schema: BTreeMap<String, String>,
+    #[serde(default)]
+    compatible: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<T>(mut self, name: &str) -> Self
+    where
+        T: Fingerprint,
+    {
+        self.schema
+            .insert(name.into(), format!("{:016x}", T::fingerprint()));
+        self.compatible
+            .insert(name.into(), format!("{:016x}", T::compatible()));
+        self
+    }
+
+    // Changes whenever any fingerprint does, compare to find out which.
+    pub fn hash(&self) -> String {
+        hash_schema(&self.schema)
+    }
+
+    // None for manifests from before compatible hashes were sent.
+    pub fn compatible_hash(&self) -> Option<String> {
+        (!self.compatible.is_empty()).then(|| hash_schema(&self.compatible))
+    }
+
+    pub fn to_value(&self) -> JsValue {
+        let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+        self.serialize(&serializer)
+            .expect("Manifest should always serialize.")
+    }
+
+    pub fn from_value(value: &JsValue) -> Option<Self> {
+        if !value.is_object() || js_sys::Array::is_array(value) {
+            return None;
+        }
+
+        serde_wasm_bindgen::from_value(value.clone()).ok()
+    }
+
+    pub fn compare(&self, remote: &Manifest) -> Result<(), SchemaMismatch> {
+        let mut names: Vec<&String> = self.schema.keys().chain(remote.schema.keys()).collect();
+        names.sort();
+        names.dedup();
+
+        let types: Vec<TypeMismatch> = names
+            .into_iter()
+            .filter_map(|name| {
+                let local = self.schema.get(name);
+                let remote = remote.schema.get(name);
+
+                (local != remote).then(|| TypeMismatch {
+                    name: name.clone(),
+                    local: local.cloned(),
+                    remote: remote.cloned(),
+                })
+            })
+            .collect();
+
+        if types.is_empty() {
+            Ok(())
+        } else {
+            Err(SchemaMismatch(types))
+        }
+    }
+}
+
+fn hash_schema(schema: &BTreeMap<String, String>) -> String {
+    let mut hasher = Hasher::new();
+    for (name, fingerprint) in schema {
+        hasher.write_str(name);
+        hasher.write_str(fingerprint);
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypeMismatch {
+    pub name: String,
+    pub local: Option<String>,
+    pub remote: Option<String>,
+}
+
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.local, &self.remote) {
+            (Some(local), Some(remote)) => {
+                write!(f, "{} (local {}, remote {})", self.name, local, remote)
+            }
+            (Some(_), None) => write!(f, "{} (missing on remote)", self.name),
+            (None, Some(_)) => write!(f, "{} (missing locally)", self.name),
+            (None, None) => write!(f, "{}", self.name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaMismatch(pub Vec<TypeMismatch>);
+
+impl fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "schema mismatch: ")?;
+        for (i, mismatch) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", mismatch)?;
+        }
+        Ok(())
+    }
+}
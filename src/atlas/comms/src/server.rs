@@ -1,23 +1,52 @@
 use atlas_comms_derive::Shareable;
-use wasm_bindgen::JsValue;
+use std::fmt;
 
-#[derive(Debug, Shareable)]
+#[derive(Clone, Debug, PartialEq, Shareable)]
 pub enum ServerMessage {
     Ok,
 }
 
-#[derive(Debug, Shareable)]
+#[derive(Clone, Debug, PartialEq, Shareable)]
 pub enum ServerError {
+    // Sent by a newer client.
+    Unsupported,
+    // Inc or Dec would take the counter past its bounds.
+    OutOfRange,
+    Surface(#[shareable(repr = "serde")] String),
+    NoAdapter,
+    Wire(#[shareable(repr = "serde")] String),
+    Cancelled,
+    #[shareable(other)]
     Unknown,
 }
 
-#[derive(Debug, Shareable)]
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::Unsupported => write!(f, "the server doesn't support this request"),
+            ServerError::OutOfRange => write!(f, "the counter is out of range"),
+            ServerError::Surface(reason) => write!(f, "can't draw on the surface: {}", reason),
+            ServerError::NoAdapter => write!(f, "no graphics adapter is available"),
+            ServerError::Wire(reason) => write!(f, "can't listen to the wire: {}", reason),
+            ServerError::Cancelled => write!(f, "the request was cancelled"),
+            ServerError::Unknown => write!(f, "unknown server error"),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+#[derive(Clone, Debug, PartialEq, Shareable)]
 pub enum ServerResponse {
     Ok(ServerMessage),
     Err(ServerError),
 }
 
-#[derive(Clone, Copy, Debug, Shareable)]
+#[derive(Clone, Copy, Debug, PartialEq, Shareable)]
+#[shareable(repr = "serde")]
 pub enum ServerEvent {
-    Count(#[shareable(repr = "serde")] u8),
+    Count(u8),
+    // Pushed by a newer server.
+    #[shareable(other)]
+    Unknown,
 }
@@ -0,0 +1,82 @@
+use crate::{port::Shareable, value::Value, wire::Wire};
+use std::fmt;
+
+// Shares the message over every wire and checks that it reads back equal and
+// that the transfer list is one postMessage accepts. Pair it with the proptest
+// feature to check every message a type can hold.
+pub fn assert_roundtrip<V, M>(message: M)
+where
+    V: Value + PartialEq,
+    M: Shareable + Clone + PartialEq,
+{
+    for wire in [Wire::Array, Wire::Binary] {
+        let (data, transfer): (V, Option<V>) = wire
+            .encode(message.clone())
+            .unwrap_or_else(|error| fail(wire, &message, error));
+        if let Some(transfer) = transfer {
+            check_transfer(wire, &message, &data, transfer);
+        }
+
+        let decoded: M = wire
+            .decode(data)
+            .unwrap_or_else(|error| fail(wire, &message, error));
+        assert!(
+            decoded == message,
+            "{:?} wire read back {:?}, expected {:?}",
+            wire,
+            decoded,
+            message
+        );
+    }
+}
+
+fn fail(wire: Wire, message: &impl fmt::Debug, error: impl fmt::Display) -> ! {
+    panic!("{:?} wire failed to share {:?}: {}", wire, message, error)
+}
+
+// Transferring a value twice or one that isn't part of the message throws.
+fn check_transfer<V>(wire: Wire, message: &impl fmt::Debug, data: &V, transfer: V)
+where
+    V: Value + PartialEq,
+{
+    let transfer = transfer.into_array().unwrap_or_else(|transfer| {
+        fail(
+            wire,
+            message,
+            format_args!("transfer list is {:?}", transfer),
+        )
+    });
+
+    for (i, item) in transfer.iter().enumerate() {
+        if transfer[..i].contains(item) {
+            fail(
+                wire,
+                message,
+                format_args!("{:?} is transferred twice", item),
+            );
+        }
+        if !reachable(data, item) {
+            fail(
+                wire,
+                message,
+                format_args!("{:?} is transferred but not shared", item),
+            );
+        }
+    }
+}
+
+fn reachable<V: Value + PartialEq>(value: &V, item: &V) -> bool {
+    if value == item || value.buffer_transfer().as_ref() == Some(item) {
+        return true;
+    }
+
+    match value.clone().into_array() {
+        Ok(items) => items.iter().any(|value| reachable(value, item)),
+        Err(value) => match value.into_map() {
+            Ok(entries) => entries
+                .iter()
+                .any(|(key, value)| reachable(key, item) || reachable(value, item)),
+            Err(_) => false,
+        },
+    }
+}
@@ -0,0 +1,43 @@
+use crate::{
+    client::ClientMessage,
+    port::{short_type_name, ShareableError},
+    rpc::{Call, Panic, Reply},
+    server::{ServerError, ServerEvent, ServerMessage, ServerResponse},
+    value::Value,
+    Payload,
+};
+
+// Implemented by derive(Shareable). Unlike the wire formats, which only Rust
+// reads, into_ts builds a value meant for JS: structs are objects and enums are
+// ["Type::Variant", value] pairs. The declaration describes that value.
+pub trait TypeScript {
+    fn declaration() -> String;
+    fn into_ts<V: Value>(self) -> Result<V, ShareableError>;
+}
+
+// Raw types are declared as their JS class. Bindings name their types after
+// the class, which an alias of one would hide.
+pub fn class_name<T: ?Sized>() -> String {
+    short_type_name::<T>()
+}
+
+// Every type exchanged between client and server, written to atlas/comms.d.ts
+// by pnpm build:atlas-types.
+pub fn declarations() -> String {
+    let declarations = [
+        Payload::<ClientMessage>::declaration(),
+        Call::<ClientMessage>::declaration(),
+        ClientMessage::declaration(),
+        ServerMessage::declaration(),
+        ServerError::declaration(),
+        ServerResponse::declaration(),
+        Reply::<ServerResponse>::declaration(),
+        Panic::declaration(),
+        ServerEvent::declaration(),
+    ];
+
+    format!(
+        "// Generated from the Rust definitions in src/atlas/comms, do not edit.\n\n{}",
+        declarations.join("\n")
+    )
+}
@@ -0,0 +1,482 @@
+use crate::{
+    limits::Limited,
+    port::{short_type_name, ShareableError},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{any::Any, fmt, rc::Rc};
+use wasm_bindgen::{JsCast, JsValue};
+
+// The tree of values derive(Shareable) writes to and reads from. JsValue is
+// what goes through postMessage, Native keeps everything in Rust so the same
+// code runs outside of a browser.
+pub trait Value: Clone + fmt::Debug + Sized + 'static {
+    fn undefined() -> Self;
+    fn is_undefined(&self) -> bool;
+
+    fn number(value: f64) -> Self;
+    fn as_number(&self) -> Option<f64>;
+
+    fn string(value: &str) -> Self;
+    fn as_string(&self) -> Option<String>;
+    // In UTF-16 code units, like JS strings.
+    fn string_length(&self) -> Option<usize>;
+
+    fn array(items: Vec<Self>) -> Self;
+    fn into_array(self) -> Result<Vec<Self>, Self>;
+    fn array_length(&self) -> Option<usize>;
+
+    fn map(entries: Vec<(Self, Self)>) -> Self;
+    fn into_map(self) -> Result<Vec<(Self, Self)>, Self>;
+    fn map_size(&self) -> Option<usize>;
+
+    // A plain object, only written for JS to read.
+    fn object(fields: Vec<(&str, Self)>) -> Self;
+
+    fn buffer<T: Element>(items: &[T]) -> Self;
+    fn into_buffer<T: Element>(self) -> Result<Vec<T>, Self>;
+    // What to transfer so the buffer is moved instead of copied.
+    fn buffer_transfer(&self) -> Option<Self>;
+
+    fn raw<T: Raw>(value: T) -> Result<Self, ShareableError>;
+    fn into_raw<T: Raw>(self) -> Result<T, ShareableError>;
+
+    fn serialize<T: Serialize + ?Sized>(value: &T) -> Result<Self, ShareableError>;
+    fn deserialize<T: DeserializeOwned>(self) -> Result<T, ShareableError>;
+}
+
+// Values shared as they are instead of being taken apart, like the DOM objects
+// that get transferred between workers.
+pub trait Raw: Clone + 'static {
+    fn into_js(self) -> Option<JsValue>;
+    fn from_js(value: JsValue) -> Option<Self>;
+}
+
+impl<T> Raw for T
+where
+    T: JsCast + Clone + 'static,
+{
+    fn into_js(self) -> Option<JsValue> {
+        Some(self.into())
+    }
+
+    fn from_js(value: JsValue) -> Option<Self> {
+        value.dyn_into().ok()
+    }
+}
+
+// The element types of typed arrays.
+pub trait Element: Copy + 'static {
+    fn to_js(items: &[Self]) -> JsValue;
+    fn from_js(value: JsValue) -> Result<Vec<Self>, JsValue>;
+    fn to_native(items: &[Self]) -> Buffer;
+    fn from_native(buffer: Buffer) -> Result<Vec<Self>, Buffer>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Buffer {
+    U8(Vec<u8>),
+    I8(Vec<i8>),
+    U16(Vec<u16>),
+    I16(Vec<i16>),
+    U32(Vec<u32>),
+    I32(Vec<i32>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+
+macro_rules! element {
+    ($($element:ty => $typed_array:ident, $variant:ident;)*) => {
+        $(
+            impl Element for $element {
+                fn to_js(items: &[Self]) -> JsValue {
+                    js_sys::$typed_array::from(items).into()
+                }
+
+                fn from_js(value: JsValue) -> Result<Vec<Self>, JsValue> {
+                    value
+                        .dyn_into::<js_sys::$typed_array>()
+                        .map(|array| array.to_vec())
+                }
+
+                fn to_native(items: &[Self]) -> Buffer {
+                    Buffer::$variant(items.to_vec())
+                }
+
+                fn from_native(buffer: Buffer) -> Result<Vec<Self>, Buffer> {
+                    match buffer {
+                        Buffer::$variant(items) => Ok(items),
+                        buffer => Err(buffer),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+element! {
+    u8 => Uint8Array, U8;
+    i8 => Int8Array, I8;
+    u16 => Uint16Array, U16;
+    i16 => Int16Array, I16;
+    u32 => Uint32Array, U32;
+    i32 => Int32Array, I32;
+    f32 => Float32Array, F32;
+    f64 => Float64Array, F64;
+}
+
+fn not_raw<T>(found: impl fmt::Debug) -> ShareableError {
+    ShareableError::incompatible_type()
+        .with_expected(short_type_name::<T>())
+        .with_found(found)
+}
+
+fn serde_failure<T: ?Sized>(error: impl fmt::Display) -> ShareableError {
+    ShareableError::serde_failure()
+        .with_expected(short_type_name::<T>())
+        .with_cause(error)
+}
+
+fn bad_serde<T>(error: impl fmt::Display) -> ShareableError {
+    ShareableError::bad_payload()
+        .with_expected(short_type_name::<T>())
+        .with_cause(error)
+}
+
+impl Value for JsValue {
+    fn undefined() -> Self {
+        JsValue::UNDEFINED
+    }
+
+    fn is_undefined(&self) -> bool {
+        JsValue::is_undefined(self)
+    }
+
+    fn number(value: f64) -> Self {
+        JsValue::from_f64(value)
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        self.as_f64()
+    }
+
+    fn string(value: &str) -> Self {
+        JsValue::from_str(value)
+    }
+
+    fn as_string(&self) -> Option<String> {
+        JsValue::as_string(self)
+    }
+
+    fn string_length(&self) -> Option<usize> {
+        self.dyn_ref::<js_sys::JsString>()
+            .map(|string| string.length() as usize)
+    }
+
+    fn array(items: Vec<Self>) -> Self {
+        items.into_iter().collect::<js_sys::Array>().into()
+    }
+
+    fn into_array(self) -> Result<Vec<Self>, Self> {
+        self.dyn_into::<js_sys::Array>()
+            .map(|array| array.iter().collect())
+    }
+
+    fn array_length(&self) -> Option<usize> {
+        self.dyn_ref::<js_sys::Array>()
+            .map(|array| array.length() as usize)
+    }
+
+    fn map(entries: Vec<(Self, Self)>) -> Self {
+        let map = js_sys::Map::new();
+        for (key, value) in entries {
+            map.set(&key, &value);
+        }
+
+        map.into()
+    }
+
+    fn into_map(self) -> Result<Vec<(Self, Self)>, Self> {
+        let map = self.dyn_into::<js_sys::Map>()?;
+        Ok(js_sys::Array::from(&map)
+            .iter()
+            .map(|entry| {
+                let entry: js_sys::Array = entry.unchecked_into();
+                (entry.get(0), entry.get(1))
+            })
+            .collect())
+    }
+
+    fn map_size(&self) -> Option<usize> {
+        self.dyn_ref::<js_sys::Map>().map(|map| map.size() as usize)
+    }
+
+    fn object(fields: Vec<(&str, Self)>) -> Self {
+        let object = js_sys::Object::new();
+        for (name, value) in fields {
+            let _ = js_sys::Reflect::set(&object, &name.into(), &value);
+        }
+
+        object.into()
+    }
+
+    fn buffer<T: Element>(items: &[T]) -> Self {
+        T::to_js(items)
+    }
+
+    fn into_buffer<T: Element>(self) -> Result<Vec<T>, Self> {
+        T::from_js(self)
+    }
+
+    fn buffer_transfer(&self) -> Option<Self> {
+        js_sys::Reflect::get(self, &"buffer".into())
+            .ok()
+            .filter(|buffer| buffer.is_instance_of::<js_sys::ArrayBuffer>())
+    }
+
+    fn raw<T: Raw>(value: T) -> Result<Self, ShareableError> {
+        value
+            .into_js()
+            .ok_or_else(|| not_raw::<JsValue>(format_args!("{}", short_type_name::<T>())))
+    }
+
+    fn into_raw<T: Raw>(self) -> Result<T, ShareableError> {
+        T::from_js(self.clone()).ok_or_else(|| not_raw::<T>(self))
+    }
+
+    fn serialize<T: Serialize + ?Sized>(value: &T) -> Result<Self, ShareableError> {
+        serde_wasm_bindgen::to_value(value).map_err(serde_failure::<T>)
+    }
+
+    fn deserialize<T: DeserializeOwned>(self) -> Result<T, ShareableError> {
+        T::deserialize(Limited(serde_wasm_bindgen::Deserializer::from(self)))
+            .map_err(bad_serde::<T>)
+    }
+}
+
+// Runs the same encoding and decoding as JsValue without a JS engine, raw
+// values are kept by reference and handed back as they were.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Native {
+    Undefined,
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Native>),
+    Map(Vec<(Native, Native)>),
+    Buffer(Buffer),
+    Raw(Object),
+}
+
+#[derive(Clone)]
+pub struct Object(Rc<dyn Any>);
+
+impl fmt::Debug for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Object")
+    }
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Native {
+    fn from_json(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => Native::Null,
+            serde_json::Value::Bool(value) => Native::Bool(value),
+            serde_json::Value::Number(value) => Native::Number(value.as_f64().unwrap_or(f64::NAN)),
+            serde_json::Value::String(value) => Native::String(value),
+            serde_json::Value::Array(items) => {
+                Native::Array(items.into_iter().map(Native::from_json).collect())
+            }
+            serde_json::Value::Object(entries) => Native::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (Native::String(key), Native::from_json(value)))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn into_json(self) -> Result<serde_json::Value, Native> {
+        Ok(match self {
+            Native::Undefined | Native::Null => serde_json::Value::Null,
+            Native::Bool(value) => serde_json::Value::Bool(value),
+            // Integers have to stay integers or serde_json won't read them
+            // back into integer types.
+            Native::Number(value) if value.fract() == 0.0 && value.abs() < 9007199254740992.0 => {
+                if value < 0.0 {
+                    (value as i64).into()
+                } else {
+                    (value as u64).into()
+                }
+            }
+            Native::Number(value) => serde_json::Number::from_f64(value)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
+            Native::String(value) => serde_json::Value::String(value),
+            Native::Array(items) => serde_json::Value::Array(
+                items
+                    .into_iter()
+                    .map(Native::into_json)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Native::Map(entries) => serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| match key {
+                        Native::String(key) => Ok((key, value.into_json()?)),
+                        key => Err(key),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            Native::Buffer(buffer) => serde_json::to_value(match buffer {
+                Buffer::U8(items) => items.into_iter().map(f64::from).collect::<Vec<_>>(),
+                Buffer::I8(items) => items.into_iter().map(f64::from).collect(),
+                Buffer::U16(items) => items.into_iter().map(f64::from).collect(),
+                Buffer::I16(items) => items.into_iter().map(f64::from).collect(),
+                Buffer::U32(items) => items.into_iter().map(f64::from).collect(),
+                Buffer::I32(items) => items.into_iter().map(f64::from).collect(),
+                Buffer::F32(items) => items.into_iter().map(f64::from).collect(),
+                Buffer::F64(items) => items,
+            })
+            .unwrap_or(serde_json::Value::Null),
+            value @ Native::Raw(_) => return Err(value),
+        })
+    }
+}
+
+impl Value for Native {
+    fn undefined() -> Self {
+        Native::Undefined
+    }
+
+    fn is_undefined(&self) -> bool {
+        matches!(self, Native::Undefined)
+    }
+
+    fn number(value: f64) -> Self {
+        Native::Number(value)
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Native::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn string(value: &str) -> Self {
+        Native::String(value.to_owned())
+    }
+
+    fn as_string(&self) -> Option<String> {
+        match self {
+            Native::String(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn string_length(&self) -> Option<usize> {
+        match self {
+            Native::String(value) => Some(value.encode_utf16().count()),
+            _ => None,
+        }
+    }
+
+    fn array(items: Vec<Self>) -> Self {
+        Native::Array(items)
+    }
+
+    fn into_array(self) -> Result<Vec<Self>, Self> {
+        match self {
+            Native::Array(items) => Ok(items),
+            value => Err(value),
+        }
+    }
+
+    fn array_length(&self) -> Option<usize> {
+        match self {
+            Native::Array(items) => Some(items.len()),
+            _ => None,
+        }
+    }
+
+    fn map(entries: Vec<(Self, Self)>) -> Self {
+        Native::Map(entries)
+    }
+
+    fn into_map(self) -> Result<Vec<(Self, Self)>, Self> {
+        match self {
+            Native::Map(entries) => Ok(entries),
+            value => Err(value),
+        }
+    }
+
+    fn map_size(&self) -> Option<usize> {
+        match self {
+            Native::Map(entries) => Some(entries.len()),
+            _ => None,
+        }
+    }
+
+    // Without a JS engine there's nothing that tells objects from maps.
+    fn object(fields: Vec<(&str, Self)>) -> Self {
+        Native::Map(
+            fields
+                .into_iter()
+                .map(|(name, value)| (Native::String(name.into()), value))
+                .collect(),
+        )
+    }
+
+    fn buffer<T: Element>(items: &[T]) -> Self {
+        Native::Buffer(T::to_native(items))
+    }
+
+    fn into_buffer<T: Element>(self) -> Result<Vec<T>, Self> {
+        match self {
+            Native::Buffer(buffer) => T::from_native(buffer).map_err(Native::Buffer),
+            value => Err(value),
+        }
+    }
+
+    fn buffer_transfer(&self) -> Option<Self> {
+        match self {
+            Native::Buffer(_) => Some(self.clone()),
+            _ => None,
+        }
+    }
+
+    fn raw<T: Raw>(value: T) -> Result<Self, ShareableError> {
+        Ok(Native::Raw(Object(Rc::new(value))))
+    }
+
+    fn into_raw<T: Raw>(self) -> Result<T, ShareableError> {
+        match &self {
+            Native::Raw(Object(object)) => object.downcast_ref::<T>().cloned(),
+            _ => None,
+        }
+        .ok_or_else(|| not_raw::<T>(self))
+    }
+
+    fn serialize<T: Serialize + ?Sized>(value: &T) -> Result<Self, ShareableError> {
+        serde_json::to_value(value)
+            .map(Native::from_json)
+            .map_err(serde_failure::<T>)
+    }
+
+    fn deserialize<T: DeserializeOwned>(self) -> Result<T, ShareableError> {
+        let json = self.into_json().map_err(|value| {
+            ShareableError::bad_payload()
+                .with_expected(short_type_name::<T>())
+                .with_found(value)
+        })?;
+
+        T::deserialize(Limited(json)).map_err(bad_serde::<T>)
+    }
+}
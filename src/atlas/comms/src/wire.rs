@@ -0,0 +1,309 @@
+use crate::{
+    limits::{self, Limited},
+    port::{short_type_name, Shareable, ShareableError},
+    value::Value,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+// Binary writes variants as discriminants and fields positionally into one
+// Uint8Array. Raw values and typed arrays can't be serialized, so they're kept
+// in a side table next to the bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Wire {
+    #[default]
+    Array,
+    Binary,
+}
+
+impl Wire {
+    pub fn encode<V, M>(self, message: M) -> Result<(V, Option<V>), ShareableError>
+    where
+        V: Value,
+        M: Shareable,
+    {
+        match self {
+            Wire::Array => encode_array(message),
+            Wire::Binary => {
+                let mut transfer = Vec::new();
+                let data = encode_binary(message, &mut transfer)?;
+                let transfer = (!transfer.is_empty()).then(|| V::array(transfer));
+
+                Ok((data, transfer))
+            }
+        }
+    }
+
+    pub fn decode<M>(self, data: impl Value) -> Result<M, ShareableError>
+    where
+        M: Shareable,
+    {
+        match self {
+            Wire::Array => decode_array(data),
+            Wire::Binary => decode_binary(data),
+        }
+    }
+}
+
+pub trait ArrayShareable: Sized {
+    fn encode<V: Value>(self, transfer: &mut Vec<V>) -> Result<V, ShareableError>;
+    fn decode<V: Value>(value: V) -> Result<Self, ShareableError>;
+}
+
+pub trait BinaryShareable: Sized {
+    fn encode<V: Value>(self, encoder: &mut Encoder<V>) -> Result<(), ShareableError>;
+    fn decode<V: Value>(decoder: &mut Decoder<V>) -> Result<Self, ShareableError>;
+}
+
+pub fn encode_array<V, M>(message: M) -> Result<(V, Option<V>), ShareableError>
+where
+    V: Value,
+    M: ArrayShareable,
+{
+    let mut transfer = Vec::new();
+    let data = message.encode(&mut transfer)?;
+    let transfer = (!transfer.is_empty()).then(|| V::array(transfer));
+
+    Ok((data, transfer))
+}
+
+pub fn decode_array<M>(data: impl Value) -> Result<M, ShareableError>
+where
+    M: ArrayShareable,
+{
+    M::decode(data)
+}
+
+// Appends what has to be transferred, so binary values can be nested in array
+// ones.
+pub fn encode_binary<V, M>(message: M, transfer: &mut Vec<V>) -> Result<V, ShareableError>
+where
+    V: Value,
+    M: BinaryShareable,
+{
+    let mut encoder = Encoder::new();
+    message
+        .encode(&mut encoder)
+        .map_err(ShareableError::within::<M>)?;
+
+    Ok(encoder.finish(transfer))
+}
+
+pub fn decode_binary<M>(data: impl Value) -> Result<M, ShareableError>
+where
+    M: BinaryShareable,
+{
+    let mut decoder = Decoder::new(data).map_err(ShareableError::within::<M>)?;
+    let message = M::decode(&mut decoder)?;
+    decoder.finish().map_err(ShareableError::within::<M>)?;
+
+    Ok(message)
+}
+
+pub struct Encoder<V> {
+    bytes: Vec<u8>,
+    table: Vec<V>,
+    transfer: Vec<V>,
+}
+
+impl<V: Value> Encoder<V> {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            table: Vec::new(),
+            transfer: Vec::new(),
+        }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn write_len(&mut self, len: usize) {
+        let mut len = len as u64;
+        while len >= 0x80 {
+            self.bytes.push((len as u8) | 0x80);
+            len >>= 7;
+        }
+        self.bytes.push(len as u8);
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write_len(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    pub fn write_serde<T>(&mut self, value: &T) -> Result<(), ShareableError>
+    where
+        T: Serialize + ?Sized,
+    {
+        let bytes = postcard::to_allocvec(value).map_err(|error| {
+            ShareableError::serde_failure()
+                .with_expected(short_type_name::<T>())
+                .with_cause(error)
+        })?;
+        self.bytes.extend_from_slice(&bytes);
+
+        Ok(())
+    }
+
+    pub fn write_raw(&mut self, value: V) {
+        self.write_len(self.table.len());
+        self.table.push(value);
+    }
+
+    pub fn transfer(&mut self, value: V) {
+        self.transfer.push(value);
+    }
+
+    fn finish(self, transfer: &mut Vec<V>) -> V {
+        let bytes = V::buffer(&self.bytes[..]);
+        transfer.extend(self.transfer);
+        transfer.extend(bytes.buffer_transfer());
+
+        V::array(vec![bytes, V::array(self.table)])
+    }
+}
+
+pub struct Decoder<V> {
+    bytes: Vec<u8>,
+    position: usize,
+    table: Vec<V>,
+}
+
+impl<V: Value> Decoder<V> {
+    fn new(data: V) -> Result<Self, ShareableError> {
+        let mut envelope = data
+            .into_array()
+            .map_err(|data| {
+                ShareableError::bad_payload()
+                    .with_expected("binary envelope")
+                    .with_found(data)
+            })?
+            .into_iter();
+        let bytes = envelope
+            .next()
+            .unwrap_or_else(V::undefined)
+            .into_buffer()
+            .map_err(|bytes| {
+                ShareableError::bad_payload()
+                    .with_expected("Uint8Array")
+                    .with_found(bytes)
+            })?;
+        let table = limits::array(envelope.next().unwrap_or_else(V::undefined))?;
+
+        Ok(Self {
+            bytes,
+            position: 0,
+            table,
+        })
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, ShareableError> {
+        let value = *self.bytes.get(self.position).ok_or_else(|| {
+            ShareableError::bad_payload()
+                .with_expected("byte")
+                .with_found(format_args!("end of payload"))
+        })?;
+        self.position += 1;
+
+        Ok(value)
+    }
+
+    // Lengths of sequences and maps, bounded by the decoding limits.
+    pub fn read_len(&mut self) -> Result<usize, ShareableError> {
+        let len = self.read_varint()?;
+        limits::length(len, limits::get().max_length, "items")?;
+
+        Ok(len)
+    }
+
+    // At most 10 bytes, the last of which only has room for the top bit.
+    fn read_varint(&mut self) -> Result<usize, ShareableError> {
+        let mut len: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            if shift == 63 && byte > 1 {
+                break;
+            }
+            len |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return usize::try_from(len).map_err(|_| {
+                    ShareableError::bad_payload()
+                        .with_expected("length")
+                        .with_found(len)
+                });
+            }
+        }
+
+        Err(ShareableError::bad_payload()
+            .with_expected("length")
+            .with_found(format_args!("overlong varint")))
+    }
+
+    pub fn read_str(&mut self) -> Result<String, ShareableError> {
+        let len = self.read_varint()?;
+        limits::length(len, limits::get().max_string_length, "bytes")?;
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| {
+                ShareableError::bad_payload()
+                    .with_expected(format_args!("string of {} bytes", len))
+                    .with_found(format_args!(
+                        "{} bytes left",
+                        self.bytes.len() - self.position
+                    ))
+            })?;
+
+        let value = std::str::from_utf8(&self.bytes[self.position..end])
+            .map_err(|error| {
+                ShareableError::bad_payload()
+                    .with_expected("utf-8 string")
+                    .with_cause(error)
+            })?
+            .to_owned();
+        self.position = end;
+
+        Ok(value)
+    }
+
+    pub fn read_serde<T>(&mut self) -> Result<T, ShareableError>
+    where
+        T: DeserializeOwned,
+    {
+        let bad = |error: postcard::Error| {
+            ShareableError::bad_payload()
+                .with_expected(short_type_name::<T>())
+                .with_cause(error)
+        };
+        let mut deserializer = postcard::Deserializer::from_bytes(&self.bytes[self.position..]);
+        let value = T::deserialize(Limited(&mut deserializer)).map_err(bad)?;
+        let rest = deserializer.finalize().map_err(bad)?;
+        self.position = self.bytes.len() - rest.len();
+
+        Ok(value)
+    }
+
+    pub fn read_raw(&mut self) -> Result<V, ShareableError> {
+        let index = self.read_varint()?;
+        self.table.get(index).cloned().ok_or_else(|| {
+            ShareableError::bad_payload()
+                .with_expected(format_args!("table index below {}", self.table.len()))
+                .with_found(index)
+        })
+    }
+
+    fn finish(self) -> Result<(), ShareableError> {
+        if self.position == self.bytes.len() || !limits::get().deny_trailing {
+            Ok(())
+        } else {
+            Err(ShareableError::bad_payload()
+                .with_expected("end of payload")
+                .with_found(format_args!(
+                    "{} trailing bytes",
+                    self.bytes.len() - self.position
+                )))
+        }
+    }
+}
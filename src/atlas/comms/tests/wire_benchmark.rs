@@ -0,0 +1,84 @@
+use atlas_comms::{js_sys, wasm_bindgen::JsValue, wire::Wire, Shareable};
+use std::collections::HashMap;
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_worker);
+
+const ITERATIONS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Shareable)]
+struct Vertex {
+    #[shareable(repr = "serde")]
+    position: [f32; 3],
+    #[shareable(repr = "serde")]
+    color: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Shareable)]
+enum Mesh {
+    Empty,
+    Indexed {
+        #[shareable(repr = "serde")]
+        label: String,
+        vertices: Vec<Vertex>,
+        #[shareable(repr = "serde")]
+        indices: Vec<u16>,
+        tags: HashMap<String, Option<Vertex>>,
+    },
+}
+
+fn mesh() -> Mesh {
+    Mesh::Indexed {
+        label: "voxelstack.me".into(),
+        vertices: (0..32)
+            .map(|i| Vertex {
+                position: [i as f32, 0.5, 1.0],
+                color: 0xff00ff,
+            })
+            .collect(),
+        indices: (0..48).collect(),
+        tags: HashMap::from([("origin".into(), None)]),
+    }
+}
+
+fn size(wire: Wire, data: &JsValue) -> u32 {
+    match wire {
+        Wire::Array => js_sys::JSON::stringify(data).unwrap().length(),
+        Wire::Binary => js_sys::Uint8Array::from(js_sys::Array::from(data).get(0)).length(),
+    }
+}
+
+fn bench(wire: Wire) {
+    let message = mesh();
+    let (data, _): (JsValue, _) = wire.encode(message.clone()).unwrap();
+    let size = size(wire, &data);
+
+    let start = js_sys::Date::now();
+    for _ in 0..ITERATIONS {
+        let (data, _): (JsValue, _) = wire.encode(message.clone()).unwrap();
+        let recovered: Mesh = wire.decode(data).unwrap();
+        assert_ne!(recovered, Mesh::Empty);
+    }
+    let elapsed = js_sys::Date::now() - start;
+
+    console_log!(
+        "{:?}: {} round trips in {:.0}ms ({:.2}µs each), {} bytes",
+        wire,
+        ITERATIONS,
+        elapsed,
+        elapsed * 1000.0 / ITERATIONS as f64,
+        size,
+    );
+}
+
+#[wasm_bindgen_test]
+#[ignore]
+fn array_wire() {
+    bench(Wire::Array);
+}
+
+#[wasm_bindgen_test]
+#[ignore]
+fn binary_wire() {
+    bench(Wire::Binary);
+}
@@ -0,0 +1,278 @@
+use crate::{
+    attrs::{parse_attributes, Attributes, ContainerAttributes},
+    shareable::UNSUPPORTED_UNION,
+    types::{is_self, parse_container, ungroup, Container},
+};
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+
+const RECURSIVE_STRUCT: &str =
+    "recursive structs can't be generated, add a strategy to the recursive field";
+const RECURSIVE_ENUM: &str =
+    "recursive enums need a variant that doesn't contain the enum to be generated";
+
+// Containers are generated with up to this many items and recursive enums up
+// to this depth, enough to reach every branch without slowing tests down.
+const MAX_ITEMS: usize = 4;
+const MAX_DEPTH: u32 = 4;
+
+// Implements proptest's Arbitrary when the proptest feature is enabled. Leaves
+// are generated by their own Arbitrary impl unless the field names a strategy,
+// which raw fields usually need. A leaf that can't be generated only leaves the
+// impl unusable, so other types still derive.
+pub fn expand_arbitrary(
+    ast: &syn::DeriveInput,
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if !cfg!(feature = "proptest") {
+        return Ok(quote! {});
+    }
+
+    let shareable_ident = &ast.ident;
+    let mut leaves = Leaves {
+        shareable_ident,
+        types: Vec::new(),
+        recursive: false,
+    };
+
+    let strategy = match &ast.data {
+        syn::Data::Struct(data_struct) => {
+            let strategy = fields_strategy(
+                quote! { #shareable_ident },
+                &data_struct.fields,
+                container,
+                &mut leaves,
+            )?;
+            if leaves.recursive {
+                return Err(syn::Error::new(shareable_ident.span(), RECURSIVE_STRUCT));
+            }
+            strategy
+        }
+        // Uninhabited, there is nothing to generate.
+        syn::Data::Enum(data_enum) if data_enum.variants.is_empty() => return Ok(quote! {}),
+        syn::Data::Enum(data_enum) => {
+            let mut variants = Vec::new();
+            let mut leaf_variants = Vec::new();
+            let mut recursive = false;
+            for variant in &data_enum.variants {
+                let variant_ident = &variant.ident;
+                leaves.recursive = false;
+                let strategy = fields_strategy(
+                    quote! { #shareable_ident::#variant_ident },
+                    &variant.fields,
+                    container,
+                    &mut leaves,
+                )?;
+                let strategy = quote! {
+                    __atlas_comms::proptest::strategy::Strategy::boxed(#strategy)
+                };
+
+                if leaves.recursive {
+                    recursive = true;
+                } else {
+                    leaf_variants.push(strategy.clone());
+                }
+                variants.push(strategy);
+            }
+
+            if !recursive {
+                quote! { __atlas_comms::proptest::strategy::Union::new(::std::vec![#(#variants),*]) }
+            } else if leaf_variants.is_empty() {
+                return Err(syn::Error::new(shareable_ident.span(), RECURSIVE_ENUM));
+            } else {
+                let items = MAX_ITEMS as u32;
+                quote! {
+                    __atlas_comms::proptest::strategy::Strategy::prop_recursive(
+                        __atlas_comms::proptest::strategy::Union::new(
+                            ::std::vec![#(#leaf_variants),*]
+                        ),
+                        #MAX_DEPTH,
+                        #MAX_DEPTH * #items,
+                        #items,
+                        |__inner| __atlas_comms::proptest::strategy::Union::new(
+                            ::std::vec![#(#variants),*]
+                        ),
+                    )
+                }
+            }
+        }
+        syn::Data::Union(_) => return Err(syn::Error::new(ast.span(), UNSUPPORTED_UNION)),
+    };
+
+    let mut generics = ast.generics.clone();
+    let where_clause = generics.make_where_clause();
+    where_clause
+        .predicates
+        .push(syn::parse_quote! { Self: ::core::fmt::Debug });
+    // Boxed strategies own their values.
+    for param in ast.generics.type_params() {
+        let ident = &param.ident;
+        where_clause
+            .predicates
+            .push(syn::parse_quote! { #ident: 'static });
+    }
+    // Higher ranked so a leaf type that isn't Arbitrary doesn't fail the
+    // derive, the bound is only checked when the impl is used.
+    for ty in &leaves.types {
+        where_clause.predicates.push(syn::parse_quote! {
+            for<'__a> #ty: __atlas_comms::proptest::arbitrary::Arbitrary
+        });
+        where_clause.predicates.push(syn::parse_quote! {
+            for<'__a> <#ty as __atlas_comms::proptest::arbitrary::Arbitrary>::Strategy: 'static
+        });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = &generics.split_for_impl();
+    let expanded = quote! {
+        impl #impl_generics
+            __atlas_comms::proptest::arbitrary::Arbitrary for #shareable_ident #ty_generics
+            #where_clause
+        {
+            type Parameters = ();
+            type Strategy = __atlas_comms::proptest::strategy::BoxedStrategy<Self>;
+
+            fn arbitrary_with(_: ()) -> Self::Strategy {
+                __atlas_comms::proptest::strategy::Strategy::boxed(#strategy)
+            }
+        }
+    };
+
+    Ok(expanded)
+}
+
+struct Leaves<'a> {
+    shareable_ident: &'a syn::Ident,
+    // Leaf types generated through their Arbitrary impl.
+    types: Vec<syn::Type>,
+    // Whether a leaf is the type being derived.
+    recursive: bool,
+}
+
+// Fields are generated as nested pairs so there is no limit on their number,
+// skipped fields are left to Default like on decode.
+fn fields_strategy(
+    path: proc_macro2::TokenStream,
+    fields: &syn::Fields,
+    container: &ContainerAttributes,
+    leaves: &mut Leaves,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let mut strategy = quote! { __atlas_comms::proptest::strategy::Just(()) };
+    let mut pattern = quote! { () };
+    let mut values = Vec::new();
+
+    for (i, field) in fields.iter().enumerate().rev() {
+        let field_attrs = parse_attributes(field, container)?;
+        if field_attrs.skip {
+            values.push(quote! { ::core::default::Default::default() });
+            continue;
+        }
+
+        let value = format_ident!("__field{}", i);
+        let field_strategy = field_strategy(&field.ty, &field_attrs, leaves)?;
+        strategy = quote! { (#field_strategy, #strategy) };
+        pattern = quote! { (#value, #pattern) };
+        values.push(quote! { #value });
+    }
+    values.reverse();
+
+    let construct = match fields {
+        syn::Fields::Named(_) => {
+            let idents = fields.iter().map(|field| &field.ident);
+            quote! { #path { #(#idents: #values),* } }
+        }
+        syn::Fields::Unnamed(_) => quote! { #path(#(#values),*) },
+        syn::Fields::Unit => quote! { #path },
+    };
+
+    Ok(quote! {
+        __atlas_comms::proptest::strategy::Strategy::prop_map(#strategy, |#pattern| #construct)
+    })
+}
+
+fn field_strategy(
+    ty: &syn::Type,
+    attrs: &Attributes,
+    leaves: &mut Leaves,
+) -> syn::Result<proc_macro2::TokenStream> {
+    // Codecs handle the whole field, so it is a single leaf.
+    if attrs.with.is_some() {
+        return Ok(leaf_strategy(ty, attrs, leaves));
+    }
+
+    let items = MAX_ITEMS;
+    let expanded = match parse_container(ty) {
+        Some(Container::Option(inner)) => {
+            let inner = field_strategy(inner, attrs, leaves)?;
+            quote! { __atlas_comms::proptest::option::of(#inner) }
+        }
+        Some(Container::Vec(inner)) => {
+            let inner = field_strategy(inner, attrs, leaves)?;
+            quote! { __atlas_comms::proptest::collection::vec(#inner, 0..=#items) }
+        }
+        Some(Container::Array(inner)) => {
+            let inner = field_strategy(inner, attrs, leaves)?;
+            quote! { __atlas_comms::proptest::array::uniform(#inner) }
+        }
+        Some(Container::Map(key, inner)) => {
+            let key = field_strategy(key, attrs, leaves)?;
+            let inner = field_strategy(inner, attrs, leaves)?;
+            quote! { __atlas_comms::proptest::collection::hash_map(#key, #inner, 0..=#items) }
+        }
+        Some(Container::Box(inner)) => {
+            let inner = field_strategy(inner, attrs, leaves)?;
+            quote! {
+                __atlas_comms::proptest::strategy::Strategy::prop_map(
+                    #inner,
+                    ::std::boxed::Box::new
+                )
+            }
+        }
+        Some(Container::Tuple(elems)) if elems.is_empty() => {
+            quote! { __atlas_comms::proptest::strategy::Just(()) }
+        }
+        Some(Container::Tuple(elems)) => {
+            let elems = elems
+                .into_iter()
+                .map(|elem| field_strategy(elem, attrs, leaves))
+                .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
+            quote! { (#(#elems,)*) }
+        }
+        None => leaf_strategy(ty, attrs, leaves),
+    };
+
+    Ok(expanded)
+}
+
+fn leaf_strategy(
+    ty: &syn::Type,
+    attrs: &Attributes,
+    leaves: &mut Leaves,
+) -> proc_macro2::TokenStream {
+    if let Some(strategy) = &attrs.strategy {
+        return quote! { #strategy() };
+    }
+
+    if is_self(leaves.shareable_ident, ty) {
+        leaves.recursive = true;
+        return quote! { ::core::clone::Clone::clone(&__inner) };
+    }
+
+    // NaN and infinities don't survive every serde format.
+    if let syn::Type::Path(path) = ungroup(ty) {
+        if path.path.is_ident("f32") || path.path.is_ident("f64") {
+            let float = path.path.get_ident();
+            return quote! {
+                __atlas_comms::proptest::num::#float::POSITIVE
+                    | __atlas_comms::proptest::num::#float::NEGATIVE
+                    | __atlas_comms::proptest::num::#float::NORMAL
+                    | __atlas_comms::proptest::num::#float::SUBNORMAL
+                    | __atlas_comms::proptest::num::#float::ZERO
+            };
+        }
+    }
+
+    if !leaves.types.contains(ty) {
+        leaves.types.push(ty.clone());
+    }
+    quote! { __atlas_comms::proptest::arbitrary::any::<#ty>() }
+}
@@ -1,112 +1,444 @@
+use std::iter;
 use syn::spanned::Spanned;
 
 pub struct Attributes {
     pub repr: Repr,
     pub transfer: bool,
+    pub default: bool,
+    pub skip: bool,
+    pub rename: Option<String>,
+    pub aliases: Vec<String>,
+    pub with: Option<syn::Path>,
+    pub validate: Option<syn::Path>,
+    pub strategy: Option<syn::Path>,
+    pub bound: Option<Vec<syn::WherePredicate>>,
+}
+
+pub struct VariantAttributes {
+    pub rename: Option<String>,
+    pub aliases: Vec<String>,
+    pub other: bool,
+}
+
+pub struct ContainerAttributes {
+    pub krate: syn::Path,
+    pub wire: Wire,
+    pub repr: Repr,
+    pub validate: Option<syn::Path>,
+    pub bound: Option<Vec<syn::WherePredicate>>,
 }
 
 #[derive(PartialEq, Eq)]
+pub enum Wire {
+    Array,
+    Binary,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Repr {
     Raw,
     Serde,
     Shareable,
+    Buffer,
+}
+
+impl Repr {
+    pub fn name(self) -> &'static str {
+        match self {
+            Repr::Raw => "raw",
+            Repr::Serde => "serde",
+            Repr::Shareable => "shareable",
+            Repr::Buffer => "buffer",
+        }
+    }
+}
+
+impl Attributes {
+    // The name written on the wire and every name accepted when reading.
+    pub fn names(&self, ident: &syn::Ident) -> (String, Vec<String>) {
+        names(ident, &self.rename, &self.aliases)
+    }
+}
+
+impl VariantAttributes {
+    pub fn names(&self, ident: &syn::Ident) -> (String, Vec<String>) {
+        names(ident, &self.rename, &self.aliases)
+    }
+}
+
+fn names(ident: &syn::Ident, rename: &Option<String>, aliases: &[String]) -> (String, Vec<String>) {
+    let name = rename.clone().unwrap_or_else(|| ident.to_string());
+    let accepted = iter::once(name.clone())
+        .chain(aliases.iter().cloned())
+        .collect();
+
+    (name, accepted)
 }
 
 struct ParseAttrs {
     repr: Option<Repr>,
     transfer: Option<bool>,
+    default: Option<bool>,
+    skip: Option<bool>,
+    rename: Option<String>,
+    aliases: Vec<String>,
+    with: Option<(proc_macro2::Span, syn::Path)>,
+    validate: Option<syn::Path>,
+    strategy: Option<syn::Path>,
+    bound: Option<Vec<syn::WherePredicate>>,
+}
+
+struct ParseVariantAttrs {
+    rename: Option<String>,
+    aliases: Vec<String>,
+    other: Option<bool>,
+}
+
+struct ParseContainerAttrs {
+    krate: Option<syn::Path>,
+    wire: Option<Wire>,
+    repr: Option<Repr>,
+    validate: Option<syn::Path>,
+    bound: Option<Vec<syn::WherePredicate>>,
 }
 
 const INVALID_FORMAT: &str =
     "unexpected token, expected attribute arguments in parentheses: #[shareable(...)]";
 const INVALID_TOKEN: &str =
     "unexpected token, expected comma separated list of ident = lit or ident";
-const INVALID_ATTR: &str = "unexpected attribute, expected ident: repr or transfer";
+const INVALID_ATTR: &str =
+    "unexpected attribute, expected ident: repr, transfer, default, skip, rename, alias, with, validate, strategy or bound";
+const INVALID_VARIANT_ATTR: &str = "unexpected attribute, expected ident: rename, alias or other";
+const INVALID_CONTAINER_ATTR: &str =
+    "unexpected attribute, expected ident: crate, wire, repr, validate or bound";
 const INVALID_REPR_END: &str = "unexpected end of attribute definition, expected: repr = \"repr\"";
-const INVALID_REPR: &str = "invalid repr, expected literal: \"raw\", \"serde\", or \"shareable\"";
+const INVALID_REPR: &str =
+    "invalid repr, expected literal: \"raw\", \"serde\", \"shareable\", or \"buffer\"";
+const INVALID_CRATE_END: &str =
+    "unexpected end of attribute definition, expected: crate = \"path\"";
+const INVALID_CRATE: &str = "invalid crate, expected a path literal: \"atlas_comms\"";
+const INVALID_WIRE_END: &str = "unexpected end of attribute definition, expected: wire = \"wire\"";
+const INVALID_WIRE: &str = "invalid wire, expected literal: \"array\" or \"binary\"";
+const INVALID_NAME_END: &str =
+    "unexpected end of attribute definition, expected: rename = \"name\" or alias = \"name\"";
+const INVALID_NAME: &str = "invalid name, expected a string literal: \"name\"";
+const INVALID_WITH_END: &str =
+    "unexpected end of attribute definition, expected: with = \"module\"";
+const INVALID_WITH: &str = "invalid with, expected a path literal: \"module\"";
+const INVALID_VALIDATE_END: &str =
+    "unexpected end of attribute definition, expected: validate = \"function\"";
+const INVALID_VALIDATE: &str = "invalid validate, expected a path literal: \"function\"";
+const INVALID_STRATEGY_END: &str =
+    "unexpected end of attribute definition, expected: strategy = \"function\"";
+const INVALID_STRATEGY: &str = "invalid strategy, expected a path literal: \"function\"";
+const INVALID_BOUND_END: &str =
+    "unexpected end of attribute definition, expected: bound = \"T: Trait, ...\"";
+const INVALID_BOUND: &str = "invalid bound, expected where predicates: \"T: Trait, ...\"";
+const CONFLICTING_WITH: &str =
+    "invalid attribute, with handles the whole field and can't be combined with repr or transfer";
+const INVALID_OTHER: &str = "invalid attribute, only unit variants can be marked other";
+const DUPLICATED_OTHER: &str = "unexpected attribute, only one variant can be marked other";
 const DUPLICATED_ATTR: &str = "unexpected attribute, attribute is already defined";
 
-pub fn parse_attributes(field: &syn::Field) -> syn::Result<Attributes> {
+const DEFAULT_CRATE: &str = "::atlas_comms";
+
+pub fn parse_attributes(
+    field: &syn::Field,
+    container: &ContainerAttributes,
+) -> syn::Result<Attributes> {
     let mut field_attrs = ParseAttrs {
         repr: None,
         transfer: None,
+        default: None,
+        skip: None,
+        rename: None,
+        aliases: Vec::new(),
+        with: None,
+        validate: None,
+        strategy: None,
+        bound: None,
     };
     let mut transfer_span: Option<proc_macro2::Span> = None;
 
-    field
-        .attrs
-        .iter()
-        .map(|attr| {
-            let syn::MetaList { path, tokens, .. } = match &attr.meta {
-                syn::Meta::Path(path) => Err(syn::Error::new(path.span(), INVALID_FORMAT)),
-                syn::Meta::List(meta_list) => Ok(meta_list),
-                syn::Meta::NameValue(name_value) => {
-                    Err(syn::Error::new(name_value.span(), INVALID_FORMAT))
-                }
-            }?;
-
-            if !path
-                .get_ident()
-                .is_some_and(|ident| ident.to_string() == "shareable")
-            {
-                return Err(syn::Error::new(path.span(), INVALID_FORMAT));
-            }
-
-            let mut token_stream = tokens.clone().into_iter();
-            while let Some(ref token) = token_stream.next() {
-                match token {
-                    proc_macro2::TokenTree::Ident(ident) => match ident.to_string().as_ref() {
-                        "transfer" => {
-                            if field_attrs.transfer.is_some() {
-                                return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
-                            }
-                            transfer_span = Some(ident.span());
-                            field_attrs.transfer = Some(true)
-                        }
-                        "repr" => {
-                            if field_attrs.repr.is_some() {
-                                return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
-                            }
-                            field_attrs.repr = Some(parse_repr(token, &mut token_stream)?)
-                        }
-                        _ => return Err(syn::Error::new(ident.span(), INVALID_ATTR)),
-                    },
-                    proc_macro2::TokenTree::Punct(punct) => {
-                        if punct.as_char() != ',' {
-                            return Err(syn::Error::new(punct.span(), INVALID_TOKEN));
-                        }
-                        continue;
-                    }
-                    _ => return Err(syn::Error::new(token.span(), INVALID_TOKEN)),
+    parse_shareable_attrs(&field.attrs, |ident, token_stream| {
+        match ident.to_string().as_ref() {
+            "transfer" => {
+                if field_attrs.transfer.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                transfer_span = Some(ident.span());
+                field_attrs.transfer = Some(true)
+            }
+            "repr" => {
+                if field_attrs.repr.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                field_attrs.repr = Some(parse_repr(ident, token_stream)?)
+            }
+            "default" => {
+                if field_attrs.default.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
                 }
+                field_attrs.default = Some(true)
             }
+            "skip" => {
+                if field_attrs.skip.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                field_attrs.skip = Some(true)
+            }
+            "rename" => {
+                if field_attrs.rename.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                field_attrs.rename = Some(parse_name(ident, token_stream)?)
+            }
+            "alias" => field_attrs.aliases.push(parse_name(ident, token_stream)?),
+            "with" => {
+                if field_attrs.with.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                let with = parse_path(ident, token_stream, INVALID_WITH_END, INVALID_WITH)?;
+                field_attrs.with = Some((ident.span(), with))
+            }
+            // Runs on the decoded value, see also the container attribute.
+            "validate" => {
+                if field_attrs.validate.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                field_attrs.validate = Some(parse_path(
+                    ident,
+                    token_stream,
+                    INVALID_VALIDATE_END,
+                    INVALID_VALIDATE,
+                )?)
+            }
+            // Generates the field in property tests, only used with the proptest feature.
+            "strategy" => {
+                if field_attrs.strategy.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                field_attrs.strategy = Some(parse_path(
+                    ident,
+                    token_stream,
+                    INVALID_STRATEGY_END,
+                    INVALID_STRATEGY,
+                )?)
+            }
+            // Replaces the bounds inferred from this field.
+            "bound" => {
+                if field_attrs.bound.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                field_attrs.bound = Some(parse_bound(ident, token_stream)?)
+            }
+            _ => return Err(syn::Error::new(ident.span(), INVALID_ATTR)),
+        }
 
-            Ok(())
-        })
-        .collect::<syn::Result<Vec<_>>>()?;
+        Ok(())
+    })?;
+
+    if let Some((with_span, _)) = &field_attrs.with {
+        if field_attrs.repr.is_some() || field_attrs.transfer.is_some() {
+            return Err(syn::Error::new(*with_span, CONFLICTING_WITH));
+        }
+    }
 
     let field_attrs = Attributes {
-        repr: field_attrs.repr.unwrap_or(Repr::Shareable),
+        repr: field_attrs.repr.unwrap_or(container.repr),
         transfer: field_attrs.transfer.unwrap_or(false),
+        default: field_attrs.default.unwrap_or(false),
+        skip: field_attrs.skip.unwrap_or(false),
+        rename: field_attrs.rename,
+        aliases: field_attrs.aliases,
+        with: field_attrs.with.map(|(_, with)| with),
+        validate: field_attrs.validate,
+        strategy: field_attrs.strategy,
+        bound: field_attrs.bound,
     };
 
-    if field_attrs.transfer && field_attrs.repr != Repr::Raw {
+    if field_attrs.transfer && !matches!(field_attrs.repr, Repr::Raw | Repr::Buffer) {
         Err(syn::Error::new(
             transfer_span.unwrap(),
-            "invalid attribute, only repr = \"raw\" fields can be transferred",
+            "invalid attribute, only repr = \"raw\" or repr = \"buffer\" fields can be transferred",
         ))
     } else {
         Ok(field_attrs)
     }
 }
 
-fn parse_repr(
-    ident: &proc_macro2::TokenTree,
+pub fn parse_variant_attributes(variant: &syn::Variant) -> syn::Result<VariantAttributes> {
+    let mut variant_attrs = ParseVariantAttrs {
+        rename: None,
+        aliases: Vec::new(),
+        other: None,
+    };
+
+    parse_shareable_attrs(&variant.attrs, |ident, token_stream| {
+        match ident.to_string().as_ref() {
+            "rename" => {
+                if variant_attrs.rename.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                variant_attrs.rename = Some(parse_name(ident, token_stream)?)
+            }
+            "alias" => variant_attrs.aliases.push(parse_name(ident, token_stream)?),
+            "other" => {
+                if variant_attrs.other.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                if !matches!(variant.fields, syn::Fields::Unit) {
+                    return Err(syn::Error::new(ident.span(), INVALID_OTHER));
+                }
+                variant_attrs.other = Some(true)
+            }
+            _ => return Err(syn::Error::new(ident.span(), INVALID_VARIANT_ATTR)),
+        }
+
+        Ok(())
+    })?;
+
+    Ok(VariantAttributes {
+        rename: variant_attrs.rename,
+        aliases: variant_attrs.aliases,
+        other: variant_attrs.other.unwrap_or(false),
+    })
+}
+
+// The catch-all variant unknown variants are read as, if the enum has one.
+pub fn parse_other_variant(data_enum: &syn::DataEnum) -> syn::Result<Option<&syn::Variant>> {
+    let mut other = None;
+    for variant in &data_enum.variants {
+        if parse_variant_attributes(variant)?.other {
+            if other.is_some() {
+                return Err(syn::Error::new(variant.span(), DUPLICATED_OTHER));
+            }
+            other = Some(variant);
+        }
+    }
+
+    Ok(other)
+}
+
+pub fn parse_container_attributes(ast: &syn::DeriveInput) -> syn::Result<ContainerAttributes> {
+    let mut container_attrs = ParseContainerAttrs {
+        krate: None,
+        wire: None,
+        repr: None,
+        validate: None,
+        bound: None,
+    };
+
+    parse_shareable_attrs(&ast.attrs, |ident, token_stream| {
+        match ident.to_string().as_ref() {
+            "crate" => {
+                if container_attrs.krate.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                let lit = parse_lit(ident, token_stream, INVALID_CRATE_END, INVALID_CRATE)?;
+                let krate = match syn::Lit::new(lit.clone()) {
+                    syn::Lit::Str(lit) => lit.parse::<syn::Path>().ok(),
+                    _ => None,
+                }
+                .ok_or(syn::Error::new(lit.span(), INVALID_CRATE))?;
+                container_attrs.krate = Some(krate)
+            }
+            "wire" => {
+                if container_attrs.wire.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                container_attrs.wire = Some(parse_wire(ident, token_stream)?)
+            }
+            // The repr of every field that doesn't set its own.
+            "repr" => {
+                if container_attrs.repr.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                container_attrs.repr = Some(parse_repr(ident, token_stream)?)
+            }
+            // Runs on the whole value once every field has been decoded.
+            "validate" => {
+                if container_attrs.validate.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                container_attrs.validate = Some(parse_path(
+                    ident,
+                    token_stream,
+                    INVALID_VALIDATE_END,
+                    INVALID_VALIDATE,
+                )?)
+            }
+            // Replaces every inferred bound.
+            "bound" => {
+                if container_attrs.bound.is_some() {
+                    return Err(syn::Error::new(ident.span(), DUPLICATED_ATTR));
+                }
+                container_attrs.bound = Some(parse_bound(ident, token_stream)?)
+            }
+            _ => return Err(syn::Error::new(ident.span(), INVALID_CONTAINER_ATTR)),
+        }
+
+        Ok(())
+    })?;
+
+    Ok(ContainerAttributes {
+        krate: container_attrs
+            .krate
+            .unwrap_or_else(|| syn::parse_str(DEFAULT_CRATE).unwrap()),
+        wire: container_attrs.wire.unwrap_or(Wire::Array),
+        repr: container_attrs.repr.unwrap_or(Repr::Shareable),
+        validate: container_attrs.validate,
+        bound: container_attrs.bound,
+    })
+}
+
+fn parse_shareable_attrs(
+    attrs: &[syn::Attribute],
+    mut parse_attr: impl FnMut(
+        &proc_macro2::Ident,
+        &mut proc_macro2::token_stream::IntoIter,
+    ) -> syn::Result<()>,
+) -> syn::Result<()> {
+    for attr in attrs {
+        if !attr.path().is_ident("shareable") {
+            continue;
+        }
+
+        let syn::MetaList { tokens, .. } = match &attr.meta {
+            syn::Meta::Path(path) => Err(syn::Error::new(path.span(), INVALID_FORMAT)),
+            syn::Meta::List(meta_list) => Ok(meta_list),
+            syn::Meta::NameValue(name_value) => {
+                Err(syn::Error::new(name_value.span(), INVALID_FORMAT))
+            }
+        }?;
+
+        let mut token_stream = tokens.clone().into_iter();
+        while let Some(ref token) = token_stream.next() {
+            match token {
+                proc_macro2::TokenTree::Ident(ident) => parse_attr(ident, &mut token_stream)?,
+                proc_macro2::TokenTree::Punct(punct) => {
+                    if punct.as_char() != ',' {
+                        return Err(syn::Error::new(punct.span(), INVALID_TOKEN));
+                    }
+                    continue;
+                }
+                _ => return Err(syn::Error::new(token.span(), INVALID_TOKEN)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_lit(
+    ident: &proc_macro2::Ident,
     token_stream: &mut proc_macro2::token_stream::IntoIter,
-) -> syn::Result<Repr> {
+    invalid_end: &str,
+    invalid_lit: &str,
+) -> syn::Result<proc_macro2::Literal> {
     let separator = token_stream
         .next()
-        .ok_or(syn::Error::new(ident.span(), INVALID_REPR_END))?;
+        .ok_or(syn::Error::new(ident.span(), invalid_end))?;
 
     let invalid_separator = match &separator {
         proc_macro2::TokenTree::Punct(punct) => punct.as_char() != '=',
@@ -120,17 +452,83 @@ fn parse_repr(
         ));
     }
 
-    let repr = token_stream
+    let lit = token_stream
         .next()
-        .ok_or(syn::Error::new(ident.span(), INVALID_REPR_END))?;
-
-    match repr {
-        proc_macro2::TokenTree::Literal(lit) => match lit.to_string().as_ref() {
-            "\"raw\"" => Ok(Repr::Raw),
-            "\"serde\"" => Ok(Repr::Serde),
-            "\"shareable\"" => Ok(Repr::Shareable),
-            _ => Err(syn::Error::new(lit.span(), INVALID_REPR)),
-        },
-        _ => Err(syn::Error::new(repr.span(), INVALID_REPR)),
+        .ok_or(syn::Error::new(ident.span(), invalid_end))?;
+
+    match lit {
+        proc_macro2::TokenTree::Literal(lit) => Ok(lit),
+        _ => Err(syn::Error::new(lit.span(), invalid_lit)),
+    }
+}
+
+fn parse_name(
+    ident: &proc_macro2::Ident,
+    token_stream: &mut proc_macro2::token_stream::IntoIter,
+) -> syn::Result<String> {
+    let lit = parse_lit(ident, token_stream, INVALID_NAME_END, INVALID_NAME)?;
+
+    match syn::Lit::new(lit.clone()) {
+        syn::Lit::Str(lit) if !lit.value().is_empty() => Ok(lit.value()),
+        _ => Err(syn::Error::new(lit.span(), INVALID_NAME)),
+    }
+}
+
+fn parse_path(
+    ident: &proc_macro2::Ident,
+    token_stream: &mut proc_macro2::token_stream::IntoIter,
+    invalid_end: &str,
+    invalid_lit: &str,
+) -> syn::Result<syn::Path> {
+    let lit = parse_lit(ident, token_stream, invalid_end, invalid_lit)?;
+
+    match syn::Lit::new(lit.clone()) {
+        syn::Lit::Str(lit) => lit.parse::<syn::Path>().ok(),
+        _ => None,
+    }
+    .ok_or(syn::Error::new(lit.span(), invalid_lit))
+}
+
+fn parse_bound(
+    ident: &proc_macro2::Ident,
+    token_stream: &mut proc_macro2::token_stream::IntoIter,
+) -> syn::Result<Vec<syn::WherePredicate>> {
+    let lit = parse_lit(ident, token_stream, INVALID_BOUND_END, INVALID_BOUND)?;
+
+    let parser =
+        syn::punctuated::Punctuated::<syn::WherePredicate, syn::Token![,]>::parse_terminated;
+    match syn::Lit::new(lit.clone()) {
+        syn::Lit::Str(lit) => lit.parse_with(parser).ok(),
+        _ => None,
+    }
+    .map(|predicates| predicates.into_iter().collect())
+    .ok_or(syn::Error::new(lit.span(), INVALID_BOUND))
+}
+
+fn parse_repr(
+    ident: &proc_macro2::Ident,
+    token_stream: &mut proc_macro2::token_stream::IntoIter,
+) -> syn::Result<Repr> {
+    let lit = parse_lit(ident, token_stream, INVALID_REPR_END, INVALID_REPR)?;
+
+    match lit.to_string().as_ref() {
+        "\"raw\"" => Ok(Repr::Raw),
+        "\"serde\"" => Ok(Repr::Serde),
+        "\"shareable\"" => Ok(Repr::Shareable),
+        "\"buffer\"" => Ok(Repr::Buffer),
+        _ => Err(syn::Error::new(lit.span(), INVALID_REPR)),
+    }
+}
+
+fn parse_wire(
+    ident: &proc_macro2::Ident,
+    token_stream: &mut proc_macro2::token_stream::IntoIter,
+) -> syn::Result<Wire> {
+    let lit = parse_lit(ident, token_stream, INVALID_WIRE_END, INVALID_WIRE)?;
+
+    match lit.to_string().as_ref() {
+        "\"array\"" => Ok(Wire::Array),
+        "\"binary\"" => Ok(Wire::Binary),
+        _ => Err(syn::Error::new(lit.span(), INVALID_WIRE)),
     }
 }
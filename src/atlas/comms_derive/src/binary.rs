@@ -0,0 +1,493 @@
+use crate::{
+    attrs::{parse_attributes, Attributes, ContainerAttributes, Repr},
+    shareable::{
+        at, at_result, field_segment, list_fields, unnamed_ident, validate_container,
+        validate_field, UNSUPPORTED_UNION,
+    },
+    types::{
+        check_map_key, check_owned, parse_buffer, parse_container, Container, UNSUPPORTED_BUFFER,
+    },
+};
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+
+// Binary is positional, so both ends must agree on the layout: skip is honored
+// but default, rename, alias and other only apply to the array wire.
+pub fn expand_binary(
+    ast: &syn::DeriveInput,
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let shareable_ident = &ast.ident;
+
+    let encode = match &ast.data {
+        syn::Data::Struct(data_struct) => encode_struct(shareable_ident, data_struct, container),
+        syn::Data::Enum(data_enum) => encode_enum(shareable_ident, data_enum, container),
+        syn::Data::Union(_) => Err(syn::Error::new(ast.span(), UNSUPPORTED_UNION)),
+    }?;
+    let encode_ident = if cfg!(feature = "verification") {
+        quote! { __encoder.write_str(stringify!(#shareable_ident)); }
+    } else {
+        quote! {}
+    };
+
+    let decode = match &ast.data {
+        syn::Data::Struct(data_struct) => decode_struct(shareable_ident, data_struct, container),
+        syn::Data::Enum(data_enum) => decode_enum(shareable_ident, data_enum, container),
+        syn::Data::Union(_) => Err(syn::Error::new(ast.span(), UNSUPPORTED_UNION)),
+    }?;
+    let decode_ident = if cfg!(feature = "verification") {
+        quote! {
+            let __ident = __decoder.read_str()?;
+            if __ident != stringify!(#shareable_ident) {
+                return ::std::result::Result::Err(
+                    __atlas_comms::port::ShareableError::incompatible_type()
+                        .with_expected(stringify!(#shareable_ident))
+                        .with_found(__ident)
+                );
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let validate = validate_container(container);
+
+    let (impl_generics, ty_generics, where_clause) = &ast.generics.split_for_impl();
+    let expanded = quote! {
+        impl #impl_generics
+            __atlas_comms::wire::BinaryShareable for #shareable_ident #ty_generics
+            #where_clause
+        {
+            fn encode<__V: __atlas_comms::value::Value>(
+                self,
+                __encoder: &mut __atlas_comms::wire::Encoder<__V>
+            ) -> ::std::result::Result<(), __atlas_comms::port::ShareableError> {
+                (|| -> ::std::result::Result<(), __atlas_comms::port::ShareableError> {
+                    #encode_ident
+                    #encode
+
+                    ::std::result::Result::Ok(())
+                })()
+                .map_err(__atlas_comms::port::ShareableError::within::<Self>)
+            }
+
+            fn decode<__V: __atlas_comms::value::Value>(
+                __decoder: &mut __atlas_comms::wire::Decoder<__V>
+            ) -> ::std::result::Result<Self, __atlas_comms::port::ShareableError> {
+                (|| -> ::std::result::Result<Self, __atlas_comms::port::ShareableError> {
+                    let __depth = __atlas_comms::limits::Depth::enter()?;
+                    #decode_ident
+                    #decode
+                })()
+                #validate
+                .map_err(__atlas_comms::port::ShareableError::within::<Self>)
+            }
+        }
+    };
+
+    Ok(expanded)
+}
+
+fn encode_fields(
+    fields: &syn::Fields,
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let encode_fields = fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let field_ident = field.ident.clone().unwrap_or(unnamed_ident(index, field));
+            let field_attrs = parse_attributes(field, container)?;
+            if field_attrs.skip {
+                return Ok(quote! {});
+            }
+            check_owned(&field.ty)?;
+
+            let encode = encode_value(&field.ty, &field_ident, &field_attrs, 0)?;
+            let encode = at(field_segment(index, field), quote! {{ #encode }});
+            Ok(quote! { #encode; })
+        })
+        .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
+
+    Ok(quote! { #(#encode_fields)* })
+}
+
+fn encode_value(
+    ty: &syn::Type,
+    value: &syn::Ident,
+    attrs: &Attributes,
+    depth: usize,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some(with) = &attrs.with {
+        return Ok(quote! {{
+            let mut __with_transfer = ::std::vec::Vec::new();
+            let __value = #with::encode(&#value, &mut __with_transfer)?;
+            for __item in __with_transfer {
+                __encoder.transfer(__item);
+            }
+            __encoder.write_raw(__value);
+        }});
+    }
+
+    if attrs.repr == Repr::Serde {
+        return Ok(quote! { __encoder.write_serde(&#value)?; });
+    }
+
+    if let (Repr::Buffer, Some(_)) = (&attrs.repr, parse_buffer(ty)) {
+        let transfer = if attrs.transfer {
+            quote! {
+                if let ::std::option::Option::Some(__buffer) = __buffer.buffer_transfer() {
+                    __encoder.transfer(__buffer);
+                }
+            }
+        } else {
+            quote! {}
+        };
+        return Ok(quote! {{
+            let __buffer = <__V as __atlas_comms::value::Value>::buffer(&#value[..]);
+            #transfer
+            __encoder.write_raw(__buffer);
+        }});
+    }
+
+    let item = format_ident!("__item{}", depth);
+    let expanded = match parse_container(ty) {
+        Some(Container::Option(inner)) => {
+            let encode_item = encode_value(inner, &item, attrs, depth + 1)?;
+            quote! {
+                match #value {
+                    ::std::option::Option::Some(#item) => {
+                        __encoder.write_u8(1);
+                        #encode_item
+                    }
+                    ::std::option::Option::None => __encoder.write_u8(0),
+                }
+            }
+        }
+        Some(Container::Vec(inner)) | Some(Container::Array(inner)) => {
+            let encode_item = encode_value(inner, &item, attrs, depth + 1)?;
+            quote! {
+                __encoder.write_len(#value.len());
+                for #item in #value {
+                    #encode_item
+                }
+            }
+        }
+        Some(Container::Map(key, inner)) => {
+            check_map_key(key)?;
+            let key = format_ident!("__key{}", depth);
+            let encode_item = encode_value(inner, &item, attrs, depth + 1)?;
+            quote! {
+                __encoder.write_len(#value.len());
+                for (#key, #item) in #value {
+                    __encoder.write_str(&#key);
+                    #encode_item
+                }
+            }
+        }
+        Some(Container::Box(inner)) => {
+            let encode_item = encode_value(inner, &item, attrs, depth + 1)?;
+            quote! {{
+                let #item = *#value;
+                #encode_item
+            }}
+        }
+        Some(Container::Tuple(elems)) => {
+            let items = (0..elems.len())
+                .map(|i| format_ident!("__item{}_{}", depth, i))
+                .collect::<Vec<_>>();
+            let encode_items = elems
+                .iter()
+                .zip(&items)
+                .map(|(elem, item)| encode_value(elem, item, attrs, depth + 1))
+                .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
+            quote! {{
+                let (#(#items,)*) = #value;
+                #(#encode_items)*
+            }}
+        }
+        None => match attrs.repr {
+            Repr::Raw => {
+                let transfer = if attrs.transfer {
+                    quote! { __encoder.transfer(::core::clone::Clone::clone(&__raw)); }
+                } else {
+                    quote! {}
+                };
+                quote! {{
+                    let __raw = <__V as __atlas_comms::value::Value>::raw(#value)?;
+                    #transfer
+                    __encoder.write_raw(__raw);
+                }}
+            }
+            Repr::Shareable => quote! {
+                __atlas_comms::wire::BinaryShareable::encode(#value, __encoder)?;
+            },
+            Repr::Buffer => return Err(syn::Error::new(ty.span(), UNSUPPORTED_BUFFER)),
+            Repr::Serde => unreachable!(),
+        },
+    };
+
+    Ok(expanded)
+}
+
+fn decode_fields(
+    structure_ident: &impl quote::ToTokens,
+    fields: &syn::Fields,
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let decode_fields = fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let field_attrs = parse_attributes(field, container)?;
+            let decode = if field_attrs.skip {
+                quote! { ::core::default::Default::default() }
+            } else {
+                at(
+                    field_segment(index, field),
+                    validate_field(
+                        &field.ty,
+                        &field_attrs,
+                        decode_value(&field.ty, &field_attrs, 0)?,
+                    ),
+                )
+            };
+
+            Ok(match &field.ident {
+                Some(field_ident) => quote! { #field_ident: #decode },
+                None => decode,
+            })
+        })
+        .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
+
+    let decode = match fields {
+        syn::Fields::Named(_) => quote! { #structure_ident { #(#decode_fields,)* } },
+        syn::Fields::Unnamed(_) => quote! { #structure_ident(#(#decode_fields,)*) },
+        syn::Fields::Unit => quote! { #structure_ident },
+    };
+    Ok(quote! { ::std::result::Result::Ok(#decode) })
+}
+
+fn decode_value(
+    ty: &syn::Type,
+    attrs: &Attributes,
+    depth: usize,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some(with) = &attrs.with {
+        return Ok(quote! { #with::decode(__decoder.read_raw()?)? });
+    }
+
+    if attrs.repr == Repr::Serde {
+        return Ok(quote! { __decoder.read_serde()? });
+    }
+
+    if let (Repr::Buffer, Some(typed_array)) = (&attrs.repr, parse_buffer(ty)) {
+        return Ok(quote! {
+            __decoder.read_raw()?.into_buffer().map_err(|__value| {
+                __atlas_comms::port::ShareableError::bad_payload()
+                    .with_expected(#typed_array)
+                    .with_found(__value)
+            })?
+        });
+    }
+
+    let expanded = match parse_container(ty) {
+        Some(Container::Option(inner)) => {
+            let decode_item = decode_value(inner, attrs, depth + 1)?;
+            quote! {
+                match __decoder.read_u8()? {
+                    0 => ::std::option::Option::None,
+                    1 => ::std::option::Option::Some(#decode_item),
+                    __tag => return ::std::result::Result::Err(
+                        __atlas_comms::port::ShareableError::bad_payload()
+                            .with_expected("option tag 0 or 1")
+                            .with_found(__tag)
+                    ),
+                }
+            }
+        }
+        Some(Container::Vec(inner)) => {
+            let vec = format_ident!("__vec{}", depth);
+            let index = format_ident!("__index{}", depth);
+            let decode_item = at(
+                quote! { format_args!("[{}]", #index) },
+                decode_value(inner, attrs, depth + 1)?,
+            );
+            quote! {{
+                let mut #vec: #ty = ::std::vec::Vec::new();
+                for #index in 0..__decoder.read_len()? {
+                    #vec.push(#decode_item);
+                }
+                #vec
+            }}
+        }
+        Some(Container::Array(inner)) => {
+            let vec = format_ident!("__vec{}", depth);
+            let index = format_ident!("__index{}", depth);
+            let decode_item = at(
+                quote! { format_args!("[{}]", #index) },
+                decode_value(inner, attrs, depth + 1)?,
+            );
+            quote! {{
+                let mut #vec: ::std::vec::Vec<#inner> = ::std::vec::Vec::new();
+                for #index in 0..__decoder.read_len()? {
+                    #vec.push(#decode_item);
+                }
+                <#ty as ::core::convert::TryFrom<::std::vec::Vec<#inner>>>::try_from(#vec)
+                    .map_err(|#vec| {
+                        __atlas_comms::port::ShareableError::bad_payload()
+                            .with_expected(stringify!(#ty))
+                            .with_found(format_args!("{} items", #vec.len()))
+                    })?
+            }}
+        }
+        Some(Container::Map(key, inner)) => {
+            check_map_key(key)?;
+            let map = format_ident!("__map{}", depth);
+            let key = format_ident!("__key{}", depth);
+            let decode_item = at(
+                quote! { format_args!("[{:?}]", #key) },
+                decode_value(inner, attrs, depth + 1)?,
+            );
+            quote! {{
+                let mut #map: #ty = ::core::default::Default::default();
+                for _ in 0..__decoder.read_len()? {
+                    let #key = __decoder.read_str()?;
+                    let __item = #decode_item;
+                    #map.insert(#key, __item);
+                }
+                #map
+            }}
+        }
+        Some(Container::Box(inner)) => {
+            let decode_item = decode_value(inner, attrs, depth + 1)?;
+            quote! { ::std::boxed::Box::new(#decode_item) }
+        }
+        Some(Container::Tuple(elems)) => {
+            let decode_items = elems
+                .iter()
+                .enumerate()
+                .map(|(i, elem)| {
+                    let segment = i.to_string();
+                    Ok(at(
+                        quote! { #segment },
+                        decode_value(elem, attrs, depth + 1)?,
+                    ))
+                })
+                .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
+            quote! { (#(#decode_items,)*) }
+        }
+        None => match attrs.repr {
+            Repr::Raw => quote! { __decoder.read_raw()?.into_raw()? },
+            Repr::Shareable => quote! {
+                __atlas_comms::wire::BinaryShareable::decode(__decoder)?
+            },
+            Repr::Buffer => return Err(syn::Error::new(ty.span(), UNSUPPORTED_BUFFER)),
+            Repr::Serde => unreachable!(),
+        },
+    };
+
+    Ok(expanded)
+}
+
+fn encode_struct(
+    shareable_ident: &syn::Ident,
+    data_struct: &syn::DataStruct,
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let syn::DataStruct { fields, .. } = data_struct;
+
+    let list_fields = list_fields(fields, container);
+    let destructure = match fields {
+        syn::Fields::Named(_) => quote! { let #shareable_ident { #list_fields } = self; },
+        syn::Fields::Unnamed(_) => quote! { let #shareable_ident(#list_fields) = self; },
+        syn::Fields::Unit => quote! {},
+    };
+    let encode_fields = encode_fields(fields, container)?;
+
+    Ok(quote! {
+        #destructure
+        #encode_fields
+    })
+}
+
+fn decode_struct(
+    shareable_ident: &syn::Ident,
+    data_struct: &syn::DataStruct,
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
+    decode_fields(shareable_ident, &data_struct.fields, container)
+}
+
+fn encode_enum(
+    shareable_ident: &syn::Ident,
+    data_enum: &syn::DataEnum,
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let encode_variants = data_enum
+        .variants
+        .iter()
+        .enumerate()
+        .map(|(discriminant, v)| {
+            let variant_ident = &v.ident;
+            let list_fields = list_fields(&v.fields, container);
+            let encode_fields = encode_fields(&v.fields, container)?;
+            let encode_fields = at(
+                quote! { stringify!(#variant_ident) },
+                quote! {{ #encode_fields }},
+            );
+
+            let pattern = match &v.fields {
+                syn::Fields::Named(_) => quote! { #shareable_ident::#variant_ident{#list_fields} },
+                syn::Fields::Unnamed(_) => {
+                    quote! { #shareable_ident::#variant_ident(#list_fields) }
+                }
+                syn::Fields::Unit => quote! { #shareable_ident::#variant_ident },
+            };
+
+            Ok(quote! {
+                #pattern => {
+                    __encoder.write_len(#discriminant);
+                    #encode_fields;
+                }
+            })
+        })
+        .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
+
+    Ok(quote! {
+        match self {
+            #(#encode_variants,)*
+        };
+    })
+}
+
+fn decode_enum(
+    shareable_ident: &syn::Ident,
+    data_enum: &syn::DataEnum,
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let decode_variants = data_enum
+        .variants
+        .iter()
+        .enumerate()
+        .map(|(discriminant, v)| {
+            let variant_ident = &v.ident;
+            let entry_ident = quote! { #shareable_ident::#variant_ident };
+            let decode_fields = decode_fields(&entry_ident, &v.fields, container)?;
+            let decode_fields = at_result(quote! { stringify!(#variant_ident) }, decode_fields);
+
+            Ok(quote! { #discriminant => #decode_fields })
+        })
+        .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
+
+    Ok(quote! {
+        match __decoder.read_len()? {
+            #(#decode_variants,)*
+            __discriminant => ::std::result::Result::Err(
+                __atlas_comms::port::ShareableError::bad_payload()
+                    .with_expected(concat!("variant of ", stringify!(#shareable_ident)))
+                    .with_found(__discriminant)
+            )
+        }
+    })
+}
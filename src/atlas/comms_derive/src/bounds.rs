@@ -0,0 +1,124 @@
+use crate::{
+    attrs::{parse_attributes, Attributes, ContainerAttributes, Repr},
+    types::{is_self, parse_container, Container},
+};
+use quote::quote;
+
+// Adds a where predicate for every field type that mentions a type parameter,
+// depending on how the field is shared, like serde does for its derives. A
+// bound attribute replaces what would be inferred, for the field or the whole
+// container.
+pub fn infer_bounds(
+    ast: &syn::DeriveInput,
+    container: &ContainerAttributes,
+) -> syn::Result<syn::Generics> {
+    let mut generics = ast.generics.clone();
+    let params: Vec<syn::Ident> = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+    if params.is_empty() {
+        return Ok(generics);
+    }
+
+    let predicates = generics.make_where_clause();
+    if let Some(bound) = &container.bound {
+        predicates.predicates.extend(bound.iter().cloned());
+        return Ok(generics);
+    }
+
+    let fields: Vec<&syn::Field> = match &ast.data {
+        syn::Data::Struct(data_struct) => data_struct.fields.iter().collect(),
+        syn::Data::Enum(data_enum) => data_enum
+            .variants
+            .iter()
+            .flat_map(|variant| variant.fields.iter())
+            .collect(),
+        syn::Data::Union(_) => Vec::new(),
+    };
+
+    for field in fields {
+        let field_attrs = parse_attributes(field, container)?;
+        if let Some(bound) = &field_attrs.bound {
+            predicates.predicates.extend(bound.iter().cloned());
+            continue;
+        }
+
+        let ty = &field.ty;
+        if !mentions(ty, &params) {
+            continue;
+        }
+
+        if field_attrs.skip || field_attrs.default {
+            predicates
+                .predicates
+                .push(syn::parse_quote! { #ty: ::core::default::Default });
+        }
+        // The codec decides what it needs.
+        if field_attrs.skip || field_attrs.with.is_some() {
+            continue;
+        }
+
+        if field_attrs.repr == Repr::Serde {
+            predicates.predicates.push(syn::parse_quote! {
+                #ty: __atlas_comms::serde::Serialize
+                    + __atlas_comms::serde::de::DeserializeOwned
+            });
+        } else {
+            leaf_bounds(
+                &ast.ident,
+                ty,
+                &field_attrs,
+                &params,
+                &mut predicates.predicates,
+            );
+        }
+    }
+
+    Ok(generics)
+}
+
+fn leaf_bounds(
+    shareable_ident: &syn::Ident,
+    ty: &syn::Type,
+    attrs: &Attributes,
+    params: &[syn::Ident],
+    predicates: &mut syn::punctuated::Punctuated<syn::WherePredicate, syn::Token![,]>,
+) {
+    match parse_container(ty) {
+        Some(Container::Option(inner))
+        | Some(Container::Vec(inner))
+        | Some(Container::Array(inner))
+        | Some(Container::Box(inner))
+        | Some(Container::Map(_, inner)) => {
+            leaf_bounds(shareable_ident, inner, attrs, params, predicates)
+        }
+        Some(Container::Tuple(elems)) => {
+            for elem in elems {
+                leaf_bounds(shareable_ident, elem, attrs, params, predicates);
+            }
+        }
+        None if !mentions(ty, params) => {}
+        None => match attrs.repr {
+            Repr::Raw => predicates.push(syn::parse_quote! { #ty: __atlas_comms::value::Raw }),
+            // Bounding the type being derived on itself would be cyclic.
+            Repr::Shareable if is_self(shareable_ident, ty) => {}
+            Repr::Shareable => {
+                predicates.push(syn::parse_quote! { #ty: __atlas_comms::port::Shareable })
+            }
+            Repr::Serde | Repr::Buffer => {}
+        },
+    }
+}
+
+fn mentions(ty: &syn::Type, params: &[syn::Ident]) -> bool {
+    fn visit(tokens: proc_macro2::TokenStream, params: &[syn::Ident]) -> bool {
+        tokens.into_iter().any(|token| match token {
+            proc_macro2::TokenTree::Ident(ident) => params.contains(&ident),
+            proc_macro2::TokenTree::Group(group) => visit(group.stream(), params),
+            _ => false,
+        })
+    }
+
+    visit(quote! { #ty }, params)
+}
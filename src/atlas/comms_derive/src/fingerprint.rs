@@ -0,0 +1,217 @@
+use crate::{
+    attrs::{
+        parse_attributes, parse_variant_attributes, Attributes, ContainerAttributes, Repr, Wire,
+    },
+    shareable::UNSUPPORTED_UNION,
+    types::{is_self, parse_buffer, parse_container, ungroup, Container},
+};
+use quote::{quote, ToTokens};
+use syn::spanned::Spanned;
+
+pub fn expand_fingerprint(
+    ast: &syn::DeriveInput,
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let shareable_ident = &ast.ident;
+    let fingerprint = hash_parts(ast, container, false)?;
+    let compatible = hash_parts(ast, container, true)?;
+
+    let (impl_generics, ty_generics, where_clause) = &ast.generics.split_for_impl();
+    let expanded = quote! {
+        impl #impl_generics
+            __atlas_comms::schema::Fingerprint for #shareable_ident #ty_generics
+            #where_clause
+        {
+            fn fingerprint() -> u64 {
+                let mut __hasher = __atlas_comms::schema::Hasher::new();
+                #(#fingerprint)*
+                __hasher.finish()
+            }
+
+            fn compatible() -> u64 {
+                let mut __hasher = __atlas_comms::schema::Hasher::new();
+                #(#compatible)*
+                __hasher.finish()
+            }
+        }
+    };
+
+    Ok(expanded)
+}
+
+// The compatible hash leaves out what an older reader of the array wire gets
+// past: trailing default fields and the variants of enums with an other one.
+fn hash_parts(
+    ast: &syn::DeriveInput,
+    container: &ContainerAttributes,
+    compatible: bool,
+) -> syn::Result<Vec<proc_macro2::TokenStream>> {
+    let shareable_ident = &ast.ident;
+
+    let mut parts: Vec<proc_macro2::TokenStream> = Vec::new();
+    if cfg!(feature = "verification") {
+        parts.push(write_str("verification"));
+    }
+    parts.push(write_str(match container.wire {
+        Wire::Array => "wire array",
+        Wire::Binary => "wire binary",
+    }));
+
+    match &ast.data {
+        syn::Data::Struct(data_struct) => {
+            parts.push(write_str("struct"));
+            parts.push(write_str(&shareable_ident.to_string()));
+            hash_fields(
+                shareable_ident,
+                &data_struct.fields,
+                container,
+                compatible,
+                &mut parts,
+            )?;
+        }
+        syn::Data::Enum(data_enum) => {
+            parts.push(write_str("enum"));
+            parts.push(write_str(&shareable_ident.to_string()));
+            let mut variants = Vec::new();
+            for variant in &data_enum.variants {
+                variants.push((variant, parse_variant_attributes(variant)?));
+            }
+            let open = compatible
+                && container.wire == Wire::Array
+                && variants.iter().any(|(_, attrs)| attrs.other);
+            for (variant, variant_attrs) in variants {
+                if open && !variant_attrs.other {
+                    continue;
+                }
+
+                let (name, _) = variant_attrs.names(&variant.ident);
+                parts.push(write_str(if variant_attrs.other {
+                    "other"
+                } else {
+                    "variant"
+                }));
+                parts.push(write_str(&name));
+                hash_fields(
+                    shareable_ident,
+                    &variant.fields,
+                    container,
+                    compatible,
+                    &mut parts,
+                )?;
+            }
+        }
+        syn::Data::Union(_) => return Err(syn::Error::new(ast.span(), UNSUPPORTED_UNION)),
+    }
+
+    Ok(parts)
+}
+
+fn write_str(value: &str) -> proc_macro2::TokenStream {
+    quote! { __hasher.write_str(#value); }
+}
+
+fn hash_fields(
+    shareable_ident: &syn::Ident,
+    fields: &syn::Fields,
+    container: &ContainerAttributes,
+    compatible: bool,
+    parts: &mut Vec<proc_macro2::TokenStream>,
+) -> syn::Result<()> {
+    parts.push(write_str(match fields {
+        syn::Fields::Named(_) => "named",
+        syn::Fields::Unnamed(_) => "unnamed",
+        syn::Fields::Unit => "unit",
+    }));
+
+    let mut attrs = Vec::new();
+    for field in fields {
+        attrs.push(parse_attributes(field, container)?);
+    }
+    let required = attrs
+        .iter()
+        .rposition(|field_attrs| !field_attrs.skip && !field_attrs.default)
+        .map_or(0, |index| index + 1);
+    let evolvable = compatible && container.wire == Wire::Array;
+
+    for (index, (field, field_attrs)) in fields.iter().zip(&attrs).enumerate() {
+        // Skipped fields are never written. Default ones are, and are read by
+        // position on the binary wire and without verification.
+        if field_attrs.skip || (evolvable && index >= required) {
+            continue;
+        }
+
+        let name = match &field.ident {
+            Some(ident) => field_attrs.names(ident).0,
+            None => index.to_string(),
+        };
+        parts.push(write_str(&format!("field {}", name)));
+        if let Some(with) = &field_attrs.with {
+            parts.push(write_str(&format!("with {}", with.to_token_stream())));
+            parts.push(write_str(&field.ty.to_token_stream().to_string()));
+            continue;
+        }
+        parts.push(write_str(field_attrs.repr.name()));
+        hash_type(shareable_ident, &field.ty, field_attrs, compatible, parts);
+    }
+
+    Ok(())
+}
+
+fn hash_type(
+    shareable_ident: &syn::Ident,
+    ty: &syn::Type,
+    attrs: &Attributes,
+    compatible: bool,
+    parts: &mut Vec<proc_macro2::TokenStream>,
+) {
+    let leaf =
+        attrs.repr == Repr::Serde || (attrs.repr == Repr::Buffer && parse_buffer(ty).is_some());
+    if leaf {
+        parts.push(write_str(&ty.to_token_stream().to_string()));
+        return;
+    }
+
+    match parse_container(ty) {
+        Some(Container::Option(inner)) => {
+            parts.push(write_str("Option"));
+            hash_type(shareable_ident, inner, attrs, compatible, parts);
+        }
+        Some(Container::Vec(inner)) => {
+            parts.push(write_str("Vec"));
+            hash_type(shareable_ident, inner, attrs, compatible, parts);
+        }
+        Some(Container::Map(key, inner)) => {
+            parts.push(write_str("Map"));
+            parts.push(write_str(&key.to_token_stream().to_string()));
+            hash_type(shareable_ident, inner, attrs, compatible, parts);
+        }
+        // Boxes are transparent on the wire.
+        Some(Container::Box(inner)) => hash_type(shareable_ident, inner, attrs, compatible, parts),
+        Some(Container::Tuple(elems)) => {
+            parts.push(write_str(&format!("tuple {}", elems.len())));
+            for elem in elems {
+                hash_type(shareable_ident, elem, attrs, compatible, parts);
+            }
+        }
+        Some(Container::Array(inner)) => {
+            if let syn::Type::Array(array) = ungroup(ty) {
+                let len = array.len.to_token_stream().to_string();
+                parts.push(write_str(&format!("array {}", len)));
+            }
+            hash_type(shareable_ident, inner, attrs, compatible, parts);
+        }
+        None if attrs.repr == Repr::Shareable && !is_self(shareable_ident, ty) => {
+            let method = if compatible {
+                quote! { compatible }
+            } else {
+                quote! { fingerprint }
+            };
+            parts.push(quote! {
+                __hasher.write_u64(<#ty as __atlas_comms::schema::Fingerprint>::#method());
+            });
+        }
+        // Recursing into the type itself would never terminate.
+        None if attrs.repr == Repr::Shareable => parts.push(write_str("self")),
+        None => parts.push(write_str(&ty.to_token_stream().to_string())),
+    }
+}
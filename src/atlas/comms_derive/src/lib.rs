@@ -1,8 +1,14 @@
 use proc_macro::TokenStream;
 use syn::{parse_macro_input, DeriveInput};
 
+mod arbitrary;
 mod attrs;
+mod binary;
+mod bounds;
+mod fingerprint;
 mod shareable;
+mod types;
+mod typescript;
 
 #[proc_macro_derive(Shareable, attributes(shareable))]
 pub fn derive_shareable(input: TokenStream) -> TokenStream {
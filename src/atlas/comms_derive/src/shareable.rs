@@ -1,209 +1,766 @@
-use crate::attrs::{parse_attributes, Repr};
-use quote::quote;
+use crate::{
+    arbitrary::expand_arbitrary,
+    attrs::{
+        parse_attributes, parse_container_attributes, parse_other_variant,
+        parse_variant_attributes, Attributes, ContainerAttributes, Repr, Wire,
+    },
+    binary::expand_binary,
+    bounds::infer_bounds,
+    fingerprint::expand_fingerprint,
+    types::{
+        check_map_key, check_owned, parse_buffer, parse_container, Container, UNSUPPORTED_BUFFER,
+    },
+    typescript::expand_typescript,
+};
+use quote::{format_ident, quote, ToTokens};
 use std::iter;
 use syn::spanned::Spanned;
 
-const UNSUPPORTED_UNION: &str = "unions are not supported by derive(Shareable)";
+pub const UNSUPPORTED_UNION: &str = "unions are not supported by derive(Shareable)";
 
 pub fn expand_derive_shareable(ast: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
     let shareable_ident = &ast.ident;
+    let container = parse_container_attributes(ast)?;
+
+    // Every impl below is generated with the inferred bounds.
+    let ast = &syn::DeriveInput {
+        generics: infer_bounds(ast, &container)?,
+        ..ast.clone()
+    };
+    let krate = &container.krate;
+
+    let (encode, decode) = match container.wire {
+        Wire::Array => expand_array(ast, &container)?,
+        Wire::Binary => (
+            quote! { __atlas_comms::wire::encode_binary(self, __transfer) },
+            quote! { __atlas_comms::wire::decode_binary(value) },
+        ),
+    };
+    // Binary containers are validated by BinaryShareable::decode instead.
+    let validate = match container.wire {
+        Wire::Array => validate_container(&container),
+        Wire::Binary => quote! {},
+    };
+    let binary = expand_binary(ast, &container)?;
+    let fingerprint = expand_fingerprint(ast, &container)?;
+    let typescript = expand_typescript(ast, &container)?;
+    let arbitrary = expand_arbitrary(ast, &container)?;
+
+    let (impl_generics, ty_generics, where_clause) = &ast.generics.split_for_impl();
+    // Debug is left to the type's own derive, Shareable only requires it.
+    let mut shareable_generics = ast.generics.clone();
+    shareable_generics
+        .make_where_clause()
+        .predicates
+        .push(syn::parse_quote! { Self: ::core::fmt::Debug });
+    let shareable_where_clause = &shareable_generics.where_clause;
+    let expanded = quote! {
+        const _: () = {
+            use #krate as __atlas_comms;
+
+            impl #impl_generics
+                ::core::convert::TryInto<(
+                    __atlas_comms::wasm_bindgen::JsValue,
+                    ::std::option::Option<__atlas_comms::wasm_bindgen::JsValue>
+                )> for #shareable_ident #ty_generics
+                #where_clause
+            {
+                type Error = __atlas_comms::port::ShareableError;
+
+                fn try_into(self) -> Result<
+                    (
+                        __atlas_comms::wasm_bindgen::JsValue,
+                        ::std::option::Option<__atlas_comms::wasm_bindgen::JsValue>
+                    ),
+                    Self::Error
+                > {
+                    __atlas_comms::wire::encode_array(self)
+                }
+            }
+
+            impl #impl_generics
+                ::core::convert::TryFrom<__atlas_comms::wasm_bindgen::JsValue>
+                for #shareable_ident #ty_generics
+                #where_clause
+            {
+                type Error = __atlas_comms::port::ShareableError;
+
+                fn try_from(
+                    value: __atlas_comms::wasm_bindgen::JsValue
+                ) -> ::std::result::Result<Self, Self::Error> {
+                    __atlas_comms::wire::decode_array(value)
+                }
+            }
+
+            impl #impl_generics
+                __atlas_comms::wire::ArrayShareable for #shareable_ident #ty_generics
+                #where_clause
+            {
+                fn encode<__V: __atlas_comms::value::Value>(
+                    self,
+                    __transfer: &mut ::std::vec::Vec<__V>
+                ) -> ::std::result::Result<__V, __atlas_comms::port::ShareableError> {
+                    (|| -> ::std::result::Result<__V, __atlas_comms::port::ShareableError> {
+                        #encode
+                    })()
+                    .map_err(__atlas_comms::port::ShareableError::within::<Self>)
+                }
+
+                fn decode<__V: __atlas_comms::value::Value>(
+                    value: __V
+                ) -> ::std::result::Result<Self, __atlas_comms::port::ShareableError> {
+                    (|| -> ::std::result::Result<Self, __atlas_comms::port::ShareableError> {
+                        #decode
+                    })()
+                    #validate
+                    .map_err(__atlas_comms::port::ShareableError::within::<Self>)
+                }
+            }
+
+            #binary
+
+            #fingerprint
+
+            #typescript
+
+            #arbitrary
+
+            impl #impl_generics
+                __atlas_comms::port::Shareable for #shareable_ident #ty_generics
+                #shareable_where_clause
+            {}
+        };
+    };
+
+    Ok(expanded)
+}
+
+fn expand_array(
+    ast: &syn::DeriveInput,
+    container: &ContainerAttributes,
+) -> syn::Result<(proc_macro2::TokenStream, proc_macro2::TokenStream)> {
+    let shareable_ident = &ast.ident;
 
     let write = match &ast.data {
-        syn::Data::Struct(data_struct) => write_shareable_struct(shareable_ident, data_struct),
-        syn::Data::Enum(data_enum) => write_shareable_enum(shareable_ident, data_enum),
+        syn::Data::Struct(data_struct) => {
+            write_shareable_struct(shareable_ident, data_struct, container)
+        }
+        syn::Data::Enum(data_enum) => write_shareable_enum(shareable_ident, data_enum, container),
         syn::Data::Union(_) => Err(syn::Error::new(ast.span(), UNSUPPORTED_UNION)),
     }?;
     let write_ident = if cfg!(feature = "verification") {
-        quote! { __payload.push(&stringify!(#shareable_ident).into()); }
+        quote! { __payload.push(__V::string(stringify!(#shareable_ident))); }
     } else {
         quote! {}
     };
 
     let read = match &ast.data {
-        syn::Data::Struct(data_struct) => read_shareable_struct(shareable_ident, data_struct),
-        syn::Data::Enum(data_enum) => read_shareable_enum(shareable_ident, data_enum),
+        syn::Data::Struct(data_struct) => {
+            read_shareable_struct(shareable_ident, data_struct, container).map(deny_trailing)
+        }
+        syn::Data::Enum(data_enum) => read_shareable_enum(shareable_ident, data_enum, container),
         syn::Data::Union(_) => Err(syn::Error::new(ast.span(), UNSUPPORTED_UNION)),
     }?;
     let read_ident = if cfg!(feature = "verification") {
+        let ident = expect_string(shift(), quote! { "type name" });
         quote! {
-            let __ident = __payload
-                .shift()
-                .as_string()
-                .ok_or(crate::port::ShareableError::BadPayload)?;
+            let __ident = #ident;
             if __ident != stringify!(#shareable_ident) {
-                return::std::result::Result::Err(crate::port::ShareableError::IncompatibleType);
+                return ::std::result::Result::Err(
+                    __atlas_comms::port::ShareableError::incompatible_type()
+                        .with_expected(stringify!(#shareable_ident))
+                        .with_found(__ident)
+                );
             }
         }
     } else {
         quote! {}
     };
 
-    let (impl_generics, ty_generics, where_clause) = &ast.generics.split_for_impl();
-    let expanded = quote! {
-        impl #impl_generics
-            ::core::convert::TryInto<(
-                wasm_bindgen::JsValue,
-                ::std::option::Option<wasm_bindgen::JsValue>
-            )> for #shareable_ident #ty_generics
-            #where_clause
-        {
-            type Error = crate::port::ShareableError;
-
-            fn try_into(self) -> Result<
-                (wasm_bindgen::JsValue, ::std::option::Option<wasm_bindgen::JsValue>),
-                Self::Error
-            > {
-                let __payload = js_sys::Array::new();
-                let mut __transfer = js_sys::Array::new();
-
-                #write_ident
-                #write
-
-                let __transfer = if __transfer.length() > 0 {
-                    ::std::option::Option::Some(__transfer.into())
-                } else {
-                    ::std::option::Option::None
-                };
+    let encode = quote! {
+        #[allow(unused_mut)]
+        let mut __payload = ::std::vec::Vec::new();
 
-                Ok((__payload.into(), __transfer))
-            }
+        #write_ident
+        #write
+
+        ::std::result::Result::Ok(__V::array(__payload))
+    };
+    let decode = quote! {
+        let __depth = __atlas_comms::limits::Depth::enter()?;
+        #[allow(unused_mut)]
+        let mut __payload = __atlas_comms::limits::array(value)?.into_iter();
+
+        #read_ident
+        #read
+    };
+
+    Ok((encode, decode))
+}
+
+// Missing values read as undefined, like they would from a JS array.
+fn shift() -> proc_macro2::TokenStream {
+    quote! { __payload.next().unwrap_or_else(__V::undefined) }
+}
+
+pub fn unnamed_ident(i: usize, f: &syn::Field) -> syn::Ident {
+    syn::Ident::new(&format!("field{}", i), f.ty.span())
+}
+
+pub fn field_segment(i: usize, f: &syn::Field) -> proc_macro2::TokenStream {
+    match &f.ident {
+        Some(ident) => quote! { stringify!(#ident) },
+        None => {
+            let i = i.to_string();
+            quote! { #i }
         }
+    }
+}
 
-        impl #impl_generics
-            ::core::convert::TryFrom<wasm_bindgen::JsValue> for #shareable_ident #ty_generics
-            #where_clause
-        {
-            type Error = crate::port::ShareableError;
+// Runs an expression that may fail with ? and prefixes its errors with a path
+// segment, so they point at the field, variant or item that failed.
+pub fn at(
+    segment: proc_macro2::TokenStream,
+    expr: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        (|| {
+            let __value = #expr;
+            ::std::result::Result::Ok::<_, __atlas_comms::port::ShareableError>(__value)
+        })()
+        .map_err(|__error| __error.at(#segment))?
+    }
+}
 
-            fn try_from(value: JsValue) -> ::std::result::Result<Self, Self::Error> {
-                let __payload: js_sys::Array = value.into();
+// Same as at, for expressions that already evaluate to a Result.
+pub fn at_result(
+    segment: proc_macro2::TokenStream,
+    expr: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        (|| -> ::std::result::Result<_, __atlas_comms::port::ShareableError> { #expr })()
+            .map_err(|__error| __error.at(#segment))
+    }
+}
 
-                #read_ident
-                #read
-            }
+// Validators take the decoded value by reference and return a Result whose
+// error is anything Display, which becomes the cause of the InvalidValue.
+pub fn validate_value(
+    validate: &syn::Path,
+    value: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        #validate(&#value).map_err(|__error| {
+            __atlas_comms::port::ShareableError::invalid_value().with_cause(__error)
+        })?;
+    }
+}
+
+pub fn validate_field(
+    ty: &syn::Type,
+    attrs: &Attributes,
+    read: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    match &attrs.validate {
+        Some(validate) => {
+            let check = validate_value(validate, quote! { __value });
+            quote! {{
+                let __value: #ty = #read;
+                #check
+                __value
+            }}
         }
+        None => read,
+    }
+}
 
-        impl #impl_generics
-            crate::port::Shareable for #shareable_ident #ty_generics
-            #where_clause
-        {}
-    };
+// Chained onto the decoding closure, so it only runs once every field is read.
+pub fn validate_container(container: &ContainerAttributes) -> proc_macro2::TokenStream {
+    match &container.validate {
+        Some(validate) => {
+            let check = validate_value(validate, quote! { __value });
+            quote! {
+                .and_then(|__value| -> ::std::result::Result<
+                    Self,
+                    __atlas_comms::port::ShareableError
+                > {
+                    #check
+                    ::std::result::Result::Ok(__value)
+                })
+            }
+        }
+        None => quote! {},
+    }
+}
 
-    Ok(expanded)
+// Values left after the last field weren't written for this type. Unknown
+// variants read as other skip the check, their fields are never read.
+fn deny_trailing(read: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    quote! {{
+        let __value = #read?;
+        __atlas_comms::limits::trailing(__payload.len())?;
+        ::std::result::Result::Ok(__value)
+    }}
 }
 
-fn unnamed_ident(i: usize, f: &syn::Field) -> syn::Ident {
-    let span = if let syn::Type::Path(path) = &f.ty {
-        path.path.segments.last().unwrap().ident.span()
-    } else {
-        todo!("might need to support other types that aren't paths");
-    };
-    syn::Ident::new(&format!("field{}", i), span)
+fn expect_string(
+    value: proc_macro2::TokenStream,
+    expected: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! { __atlas_comms::limits::string(#value, #expected)? }
 }
 
-fn write_field((index, field): (usize, &syn::Field)) -> syn::Result<proc_macro2::TokenStream> {
+fn write_field(
+    (index, field): (usize, &syn::Field),
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
     let is_named = field.ident.is_some();
     let field_ident = field.ident.clone().unwrap_or(unnamed_ident(index, field));
-    let field_attrs = parse_attributes(field)?;
+    let field_attrs = parse_attributes(field, container)?;
+    if field_attrs.skip {
+        return Ok(quote! {});
+    }
+    check_owned(&field.ty)?;
 
     let mut statements: Vec<proc_macro2::TokenStream> = Vec::new();
 
-    if cfg!(feature = "verification") && is_named {
-        statements.push(quote! { __payload.push(&stringify!(#field_ident).into()); });
+    if cfg!(feature = "verification") {
+        let tag = if is_named {
+            field_attrs.names(&field_ident).0
+        } else {
+            position_tag(&field.ty, &field_attrs)
+        };
+        statements.push(quote! { __payload.push(__V::string(#tag)); });
     }
 
-    match field_attrs.repr {
-        Repr::Raw => {
-            if field_attrs.transfer {
-                statements.push(quote! { __transfer.push(&#field_ident.clone().into()); });
-            }
-            statements.push(quote! { __payload.push(&#field_ident.into()); });
-        }
-        Repr::Serde => statements.push(
-            quote! { __payload.push(&serde_wasm_bindgen::to_value(&#field_ident)
-                .map_err(|_| crate::port::ShareableError::SerdeFailure)?);
-            },
-        ),
-        Repr::Shareable => statements.push(quote! {
-            let (__data, __nested_transfer) = #field_ident.try_into()?;
-            match __nested_transfer {
-                ::std::option::Option::Some(__nested_transfer) => {
-                    __transfer = __transfer.concat(&__nested_transfer.into());
-                }
-                _ => (),
-            };
-            __payload.push(&__data);
-        }),
-    };
+    let write = at(
+        field_segment(index, field),
+        write_value(&field.ty, &field_ident, &field_attrs, 0)?,
+    );
+    statements.push(quote! { __payload.push(#write); });
 
     Ok(quote! { #(#statements)* })
 }
 
-fn read_field(field: &syn::Field) -> syn::Result<proc_macro2::TokenStream> {
-    let field_ident = &field.ident;
-    let field_attrs = parse_attributes(field)?;
+fn write_value(
+    ty: &syn::Type,
+    value: &syn::Ident,
+    attrs: &Attributes,
+    depth: usize,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some(with) = &attrs.with {
+        return Ok(quote! { #with::encode(&#value, __transfer)? });
+    }
 
-    let expanded = if field_ident.is_some() {
-        let read = if cfg!(feature = "verification") {
-            quote! {
-                __fields
-                    .remove(stringify!(#field_ident))
-                    .ok_or(crate::port::ShareableError::BadPayload)?
-            }
+    if attrs.repr == Repr::Serde {
+        return Ok(quote! { __V::serialize(&#value)? });
+    }
+
+    if let (Repr::Buffer, Some(_)) = (&attrs.repr, parse_buffer(ty)) {
+        let transfer = if attrs.transfer {
+            quote! { __transfer.extend(__buffer.buffer_transfer()); }
         } else {
-            quote! { __payload.shift() }
+            quote! {}
         };
+        return Ok(quote! {{
+            let __buffer = __V::buffer(&#value[..]);
+            #transfer
+            __buffer
+        }});
+    }
 
-        match field_attrs.repr {
-            Repr::Raw => quote! {
-                #field_ident: #read.into()
-            },
-            Repr::Serde => quote! {
-                #field_ident: serde_wasm_bindgen::from_value(#read)
-                    .map_err(|_| crate::port::ShareableError::BadPayload)?
-            },
+    let item = format_ident!("__item{}", depth);
+    let expanded = match parse_container(ty) {
+        Some(Container::Option(inner)) => {
+            let write_item = write_value(inner, &item, attrs, depth + 1)?;
+            quote! {
+                match #value {
+                    ::std::option::Option::Some(#item) => #write_item,
+                    ::std::option::Option::None => __V::undefined(),
+                }
+            }
+        }
+        Some(Container::Vec(inner)) | Some(Container::Array(inner)) => {
+            let array = format_ident!("__array{}", depth);
+            let write_item = write_value(inner, &item, attrs, depth + 1)?;
+            quote! {{
+                let mut #array = ::std::vec::Vec::new();
+                for #item in #value {
+                    #array.push(#write_item);
+                }
+                __V::array(#array)
+            }}
+        }
+        Some(Container::Map(key, inner)) => {
+            check_map_key(key)?;
+            let map = format_ident!("__map{}", depth);
+            let key = format_ident!("__key{}", depth);
+            let write_item = write_value(inner, &item, attrs, depth + 1)?;
+            quote! {{
+                let mut #map = ::std::vec::Vec::new();
+                for (#key, #item) in #value {
+                    #map.push((__V::string(&#key), #write_item));
+                }
+                __V::map(#map)
+            }}
+        }
+        Some(Container::Box(inner)) => {
+            let write_item = write_value(inner, &item, attrs, depth + 1)?;
+            quote! {{
+                let #item = *#value;
+                #write_item
+            }}
+        }
+        Some(Container::Tuple(elems)) => {
+            let items = (0..elems.len())
+                .map(|i| format_ident!("__item{}_{}", depth, i))
+                .collect::<Vec<_>>();
+            let write_items = elems
+                .iter()
+                .zip(&items)
+                .map(|(elem, item)| write_value(elem, item, attrs, depth + 1))
+                .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
+            quote! {{
+                let (#(#items,)*) = #value;
+                __V::array(::std::vec![#(#write_items),*])
+            }}
+        }
+        None => match attrs.repr {
+            Repr::Raw => {
+                let transfer = if attrs.transfer {
+                    quote! { __transfer.push(::core::clone::Clone::clone(&__raw)); }
+                } else {
+                    quote! {}
+                };
+                quote! {{
+                    let __raw = __V::raw(#value)?;
+                    #transfer
+                    __raw
+                }}
+            }
             Repr::Shareable => quote! {
-                #field_ident: #read.try_into()?
+                __atlas_comms::wire::ArrayShareable::encode(#value, &mut *__transfer)?
             },
-        }
+            Repr::Buffer => return Err(syn::Error::new(ty.span(), UNSUPPORTED_BUFFER)),
+            Repr::Serde => unreachable!(),
+        },
+    };
+
+    Ok(expanded)
+}
+
+// Under verification unnamed fields are written after a tag naming their repr
+// and type, so fields that were reordered or changed don't read as each other.
+fn position_tag(ty: &syn::Type, attrs: &Attributes) -> String {
+    let ty = ty.to_token_stream().to_string().replace(' ', "");
+    match &attrs.with {
+        Some(with) => format!(
+            "with {} {}",
+            with.to_token_stream().to_string().replace(' ', ""),
+            ty
+        ),
+        None => format!("{} {}", attrs.repr.name(), ty),
+    }
+}
+
+// Named fields are looked up by name under verification and read in order
+// otherwise, so without verification new fields have to be appended.
+fn read_field(
+    (index, field): (usize, &syn::Field),
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let field_attrs = parse_attributes(field, container)?;
+    let default = quote! { ::core::default::Default::default() };
+    let shift = shift();
+
+    let read = if field_attrs.skip {
+        default
     } else {
-        match field_attrs.repr {
-            Repr::Raw => quote! { __payload.shift().into() },
-            Repr::Serde => {
-                quote! { serde_wasm_bindgen::from_value(__payload.shift())
-                    .map_err(|_| crate::port::ShareableError::BadPayload)?
+        let read = read_value(&field.ty, quote! { __field }, &field_attrs, 0)?;
+        let read = match &field.ident {
+            Some(field_ident) if cfg!(feature = "verification") => {
+                let (name, accepted) = field_attrs.names(field_ident);
+                let lookup = quote! {
+                    [#(#accepted),*]
+                        .iter()
+                        .find_map(|__name| __fields.remove(*__name))
+                };
+
+                if field_attrs.default {
+                    quote! {
+                        match #lookup {
+                            ::std::option::Option::Some(__field) => #read,
+                            ::std::option::Option::None => #default,
+                        }
+                    }
+                } else {
+                    quote! {{
+                        let __field = #lookup.ok_or_else(|| {
+                            __atlas_comms::port::ShareableError::bad_payload()
+                                .with_expected(concat!("field ", #name))
+                        })?;
+                        #read
+                    }}
                 }
             }
-            Repr::Shareable => quote! { __payload.shift().try_into()? },
+            None if cfg!(feature = "verification") => {
+                let tag = position_tag(&field.ty, &field_attrs);
+                let check = quote! {
+                    let __tag = __atlas_comms::limits::string(__tag, "field tag")?;
+                    if __tag != #tag {
+                        return ::std::result::Result::Err(
+                            __atlas_comms::port::ShareableError::incompatible_type()
+                                .with_expected(#tag)
+                                .with_found(__tag)
+                        );
+                    }
+                    let __field = #shift;
+                };
+
+                // Fields missing from an older writer are only at the end.
+                if field_attrs.default {
+                    quote! {{
+                        let __tag = #shift;
+                        if __tag.is_undefined() {
+                            #default
+                        } else {
+                            #check
+                            if __field.is_undefined() {
+                                #default
+                            } else {
+                                #read
+                            }
+                        }
+                    }}
+                } else {
+                    quote! {{
+                        let __tag = #shift;
+                        #check
+                        #read
+                    }}
+                }
+            }
+            _ => {
+                if field_attrs.default {
+                    quote! {{
+                        let __field = #shift;
+                        if __field.is_undefined() {
+                            #default
+                        } else {
+                            #read
+                        }
+                    }}
+                } else {
+                    quote! {{
+                        let __field = #shift;
+                        #read
+                    }}
+                }
+            }
+        };
+
+        at(
+            field_segment(index, field),
+            validate_field(&field.ty, &field_attrs, read),
+        )
+    };
+
+    let expanded = match &field.ident {
+        Some(field_ident) => quote! { #field_ident: #read },
+        None => read,
+    };
+    Ok(expanded)
+}
+
+fn read_value(
+    ty: &syn::Type,
+    value: proc_macro2::TokenStream,
+    attrs: &Attributes,
+    depth: usize,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some(with) = &attrs.with {
+        return Ok(quote! { #with::decode(#value)? });
+    }
+
+    if attrs.repr == Repr::Serde {
+        return Ok(quote! {{
+            let __leaf: __V = #value;
+            __atlas_comms::limits::serde(&__leaf)?;
+            __leaf.deserialize()?
+        }});
+    }
+
+    if let (Repr::Buffer, Some(typed_array)) = (&attrs.repr, parse_buffer(ty)) {
+        return Ok(quote! {{
+            let __leaf: __V = #value;
+            __leaf.into_buffer().map_err(|__value| {
+                __atlas_comms::port::ShareableError::bad_payload()
+                    .with_expected(#typed_array)
+                    .with_found(__value)
+            })?
+        }});
+    }
+
+    let item = format_ident!("__item{}", depth);
+    let expanded = match parse_container(ty) {
+        Some(Container::Option(inner)) => {
+            let read_item = read_value(inner, quote! { #item }, attrs, depth + 1)?;
+            quote! {{
+                let #item: __V = #value;
+                if #item.is_undefined() {
+                    ::std::option::Option::None
+                } else {
+                    ::std::option::Option::Some(#read_item)
+                }
+            }}
         }
+        Some(Container::Vec(inner)) => {
+            let vec = format_ident!("__vec{}", depth);
+            let index = format_ident!("__index{}", depth);
+            let read_item = at(
+                quote! { format_args!("[{}]", #index) },
+                read_value(inner, quote! { #item }, attrs, depth + 1)?,
+            );
+            quote! {{
+                let mut #vec: #ty = ::std::vec::Vec::new();
+                for (#index, #item) in __atlas_comms::limits::array(#value)?
+                    .into_iter()
+                    .enumerate()
+                {
+                    #vec.push(#read_item);
+                }
+                #vec
+            }}
+        }
+        Some(Container::Map(key, inner)) => {
+            check_map_key(key)?;
+            let map = format_ident!("__map{}", depth);
+            let entry = format_ident!("__entry{}", depth);
+            let key = format_ident!("__key{}", depth);
+            let read_key = expect_string(quote! { #key }, quote! { "string key" });
+            let read_item = at(
+                quote! { format_args!("[{:?}]", #key) },
+                read_value(inner, quote! { #entry }, attrs, depth + 1)?,
+            );
+            quote! {{
+                let mut #map: #ty = ::core::default::Default::default();
+                for (#key, #entry) in __atlas_comms::limits::map(#value)? {
+                    let #key = #read_key;
+                    let __item = #read_item;
+                    #map.insert(#key, __item);
+                }
+                #map
+            }}
+        }
+        Some(Container::Box(inner)) => {
+            let read_item = read_value(inner, value, attrs, depth + 1)?;
+            quote! { ::std::boxed::Box::new(#read_item) }
+        }
+        Some(Container::Tuple(elems)) => {
+            let array = format_ident!("__array{}", depth);
+            let len = elems.len();
+            let read_items = elems
+                .iter()
+                .enumerate()
+                .map(|(i, elem)| {
+                    let segment = i.to_string();
+                    let read_item = read_value(
+                        elem,
+                        quote! { #array.next().unwrap_or_else(__V::undefined) },
+                        attrs,
+                        depth + 1,
+                    )?;
+                    Ok(at(quote! { #segment }, read_item))
+                })
+                .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
+            quote! {{
+                let mut #array = __atlas_comms::limits::tuple(#value, #len)?.into_iter();
+                (#(#read_items,)*)
+            }}
+        }
+        Some(Container::Array(inner)) => {
+            let vec = format_ident!("__vec{}", depth);
+            let index = format_ident!("__index{}", depth);
+            let read_item = at(
+                quote! { format_args!("[{}]", #index) },
+                read_value(inner, quote! { #item }, attrs, depth + 1)?,
+            );
+            quote! {{
+                let mut #vec: ::std::vec::Vec<#inner> = ::std::vec::Vec::new();
+                for (#index, #item) in __atlas_comms::limits::array(#value)?
+                    .into_iter()
+                    .enumerate()
+                {
+                    #vec.push(#read_item);
+                }
+                <#ty as ::core::convert::TryFrom<::std::vec::Vec<#inner>>>::try_from(#vec)
+                    .map_err(|#vec| {
+                        __atlas_comms::port::ShareableError::bad_payload()
+                            .with_expected(stringify!(#ty))
+                            .with_found(format_args!("{} items", #vec.len()))
+                    })?
+            }}
+        }
+        None => match attrs.repr {
+            Repr::Raw => quote! { #value.into_raw()? },
+            Repr::Shareable => quote! { __atlas_comms::wire::ArrayShareable::decode(#value)? },
+            Repr::Buffer => return Err(syn::Error::new(ty.span(), UNSUPPORTED_BUFFER)),
+            Repr::Serde => unreachable!(),
+        },
     };
+
     Ok(expanded)
 }
 
-fn list_fields(fields: &syn::Fields) -> proc_macro2::TokenStream {
+pub fn list_fields(
+    fields: &syn::Fields,
+    container: &ContainerAttributes,
+) -> proc_macro2::TokenStream {
+    // Skipped fields are never written, so they aren't bound either. Invalid
+    // attributes are reported when the field itself is expanded.
+    let is_skipped = |f: &syn::Field| {
+        parse_attributes(f, container)
+            .map(|a| a.skip)
+            .unwrap_or(false)
+    };
+
     let field_names: Box<dyn Iterator<Item = proc_macro2::TokenStream>> = match &fields {
-        syn::Fields::Named(ref fields_named) => Box::new(fields_named.named.iter().map(|f| {
+        syn::Fields::Named(ref fields_named) => Box::new(fields_named.named.iter().map(move |f| {
             let field_name = &f.ident;
-            quote! {#field_name}
-        })),
-        syn::Fields::Unnamed(ref fields_unnamed) => {
-            Box::new(fields_unnamed.unnamed.iter().enumerate().map(|(i, f)| {
-                let field_name = unnamed_ident(i, f);
+            if is_skipped(f) {
+                quote! {#field_name: _}
+            } else {
                 quote! {#field_name}
-            }))
-        }
+            }
+        })),
+        syn::Fields::Unnamed(ref fields_unnamed) => Box::new(
+            fields_unnamed
+                .unnamed
+                .iter()
+                .enumerate()
+                .map(move |(i, f)| {
+                    if is_skipped(f) {
+                        return quote! {_};
+                    }
+                    let field_name = unnamed_ident(i, f);
+                    quote! {#field_name}
+                }),
+        ),
         syn::Fields::Unit => Box::new(iter::empty()),
     };
 
     quote! { #(#field_names,)* }
 }
 
-fn write_fields_named(fields_named: &syn::FieldsNamed) -> syn::Result<proc_macro2::TokenStream> {
+fn write_fields_named(
+    fields_named: &syn::FieldsNamed,
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
     let write_fields = fields_named
         .named
         .iter()
         .enumerate()
-        .map(write_field)
+        .map(|field| write_field(field, container))
         .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
 
     Ok(quote! { #(#write_fields)* })
@@ -211,40 +768,75 @@ fn write_fields_named(fields_named: &syn::FieldsNamed) -> syn::Result<proc_macro
 
 fn write_fields_unnamed(
     fields_unnamed: &syn::FieldsUnnamed,
+    container: &ContainerAttributes,
 ) -> syn::Result<proc_macro2::TokenStream> {
     let write_fields = fields_unnamed
         .unnamed
         .iter()
         .enumerate()
-        .map(write_field)
+        .map(|field| write_field(field, container))
         .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
 
-    Ok(quote! { #(#write_fields)* })
+    let write_arity = if cfg!(feature = "verification") {
+        let (_, arity) = arity(fields_unnamed, container)?;
+        let arity = arity as f64;
+        quote! { __payload.push(__V::number(#arity)); }
+    } else {
+        quote! {}
+    };
+
+    Ok(quote! {
+        #write_arity
+        #(#write_fields)*
+    })
+}
+
+// How many unnamed fields a reader needs and how many it can read, skipped
+// fields aren't written and default ones can be missing.
+fn arity(
+    fields_unnamed: &syn::FieldsUnnamed,
+    container: &ContainerAttributes,
+) -> syn::Result<(u32, u32)> {
+    let (mut required, mut total) = (0, 0);
+    for field in &fields_unnamed.unnamed {
+        let field_attrs = parse_attributes(field, container)?;
+        if field_attrs.skip {
+            continue;
+        }
+        total += 1;
+        if !field_attrs.default {
+            required += 1;
+        }
+    }
+
+    Ok((required, total))
 }
 
 fn read_fields_named(
-    structure_ident: &impl quote::ToTokens,
+    structure_ident: &impl ToTokens,
     fields_named: &syn::FieldsNamed,
+    container: &ContainerAttributes,
 ) -> syn::Result<proc_macro2::TokenStream> {
-    let field_count = fields_named.named.len();
     let read_fields = fields_named
         .named
         .iter()
-        .map(read_field)
+        .enumerate()
+        .map(|field| read_field(field, container))
         .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
 
     let read = if cfg!(feature = "verification") {
+        let read_field_name = expect_string(shift(), quote! { "field name" });
+        let shift = shift();
         quote! {::std::result::Result::Ok({
-            let mut __fields =::std::collections::HashMap::<String, JsValue>::new();
-            for _ in 0..#field_count {
-                let __field_name = __payload
-                    .shift()
-                    .as_string()
-                    .ok_or(crate::port::ShareableError::BadPayload)?;
-                __fields.insert(__field_name, __payload.shift());
+            let mut __fields = ::std::collections::HashMap::<String, __V>::new();
+            while __payload.len() > 0 {
+                let __field_name = #read_field_name;
+                __fields.insert(__field_name, #shift);
             }
 
-            #structure_ident { #(#read_fields,)* }
+            let __value = #structure_ident { #(#read_fields,)* };
+            __atlas_comms::limits::unknown_fields(&__fields)?;
+            __value
         })}
     } else {
         quote! {::std::result::Result::Ok(#structure_ident { #(#read_fields,)* })}
@@ -253,36 +845,69 @@ fn read_fields_named(
 }
 
 fn read_fields_unnamed(
-    structure_ident: &impl quote::ToTokens,
+    structure_ident: &impl ToTokens,
     fields_unnamed: &syn::FieldsUnnamed,
+    container: &ContainerAttributes,
 ) -> syn::Result<proc_macro2::TokenStream> {
     let read_fields = fields_unnamed
         .unnamed
         .iter()
-        .map(read_field)
+        .enumerate()
+        .map(|field| read_field(field, container))
         .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
 
-    let read = quote! {std::result::Result::Ok(
-        #structure_ident(#(#read_fields,)*)
-    )};
+    let read = if cfg!(feature = "verification") {
+        let (required, total) = arity(fields_unnamed, container)?;
+        let expected = if required == total {
+            format!("{} fields", total)
+        } else {
+            format!("{} to {} fields", required, total)
+        };
+        let shift = shift();
+        quote! {{
+            let __arity = #shift;
+            match __arity.as_number() {
+                ::std::option::Option::Some(__arity)
+                    if (#required as f64..=#total as f64).contains(&__arity) =>
+                {
+                    ::std::result::Result::Ok(#structure_ident(#(#read_fields,)*))
+                }
+                ::std::option::Option::Some(__arity) => ::std::result::Result::Err(
+                    __atlas_comms::port::ShareableError::incompatible_type()
+                        .with_expected(#expected)
+                        .with_found(format_args!("{} fields", __arity))
+                ),
+                ::std::option::Option::None => ::std::result::Result::Err(
+                    __atlas_comms::port::ShareableError::bad_payload()
+                        .with_expected("field count")
+                        .with_found(__arity)
+                ),
+            }
+        }}
+    } else {
+        quote! {std::result::Result::Ok(
+            #structure_ident(#(#read_fields,)*)
+        )}
+    };
     Ok(read)
 }
 
 fn write_shareable_struct(
     shareable_ident: &syn::Ident,
     data_struct: &syn::DataStruct,
+    container: &ContainerAttributes,
 ) -> syn::Result<proc_macro2::TokenStream> {
     let syn::DataStruct { fields, .. } = data_struct;
 
-    let list_fields = list_fields(fields);
+    let list_fields = list_fields(fields, container);
     let (destructure, write_fields) = match &fields {
         syn::Fields::Named(ref fields_named) => (
             quote! { let #shareable_ident { #list_fields } = self; },
-            write_fields_named(fields_named)?,
+            write_fields_named(fields_named, container)?,
         ),
         syn::Fields::Unnamed(ref fields_unnamed) => (
             quote! { let #shareable_ident(#list_fields) = self; },
-            write_fields_unnamed(fields_unnamed)?,
+            write_fields_unnamed(fields_unnamed, container)?,
         ),
         syn::Fields::Unit => (quote! {}, quote! {}),
     };
@@ -298,13 +923,16 @@ fn write_shareable_struct(
 fn read_shareable_struct(
     shareable_ident: &syn::Ident,
     data_struct: &syn::DataStruct,
+    container: &ContainerAttributes,
 ) -> syn::Result<proc_macro2::TokenStream> {
     let syn::DataStruct { fields, .. } = data_struct;
 
     let make_struct = match &fields {
-        syn::Fields::Named(ref fields_named) => read_fields_named(shareable_ident, fields_named)?,
+        syn::Fields::Named(ref fields_named) => {
+            read_fields_named(shareable_ident, fields_named, container)?
+        }
         syn::Fields::Unnamed(ref fields_unnamed) => {
-            read_fields_unnamed(shareable_ident, fields_unnamed)?
+            read_fields_unnamed(shareable_ident, fields_unnamed, container)?
         }
         syn::Fields::Unit => quote! { ::std::result::Result::Ok(#shareable_ident) },
     };
@@ -315,6 +943,7 @@ fn read_shareable_struct(
 fn write_shareable_enum(
     shareable_ident: &syn::Ident,
     data_enum: &syn::DataEnum,
+    container: &ContainerAttributes,
 ) -> syn::Result<proc_macro2::TokenStream> {
     let syn::DataEnum { variants, .. } = data_enum;
 
@@ -322,30 +951,37 @@ fn write_shareable_enum(
         .iter()
         .map(|v| {
             let variant_ident = &v.ident;
-            let list_fields = list_fields(&v.fields);
+            let (name, _) = parse_variant_attributes(v)?.names(variant_ident);
+            let list_fields = list_fields(&v.fields, container);
 
             let write_variant = match &v.fields {
                 syn::Fields::Named(ref fields_named) => {
-                    let write_fields = write_fields_named(fields_named)?;
+                    let write_fields = at(quote! { stringify!(#variant_ident) }, {
+                        let write_fields = write_fields_named(fields_named, container)?;
+                        quote! { { #write_fields } }
+                    });
                     quote! {
                         #shareable_ident::#variant_ident{#list_fields} => {
-                            __payload.push(&stringify!(#variant_ident).into());
-                            #write_fields
+                            __payload.push(__V::string(#name));
+                            #write_fields;
                         }
                     }
                 }
                 syn::Fields::Unnamed(ref fields_unnamed) => {
-                    let write_fields = write_fields_unnamed(fields_unnamed)?;
+                    let write_fields = at(quote! { stringify!(#variant_ident) }, {
+                        let write_fields = write_fields_unnamed(fields_unnamed, container)?;
+                        quote! { { #write_fields } }
+                    });
                     quote! {
                         #shareable_ident::#variant_ident(#list_fields) => {
-                            __payload.push(&stringify!(#variant_ident).into());
-                            #write_fields
+                            __payload.push(__V::string(#name));
+                            #write_fields;
                         }
                     }
                 }
                 syn::Fields::Unit => quote! {
                     #shareable_ident::#variant_ident => {
-                        __payload.push(&stringify!(#variant_ident).into());
+                        __payload.push(__V::string(#name));
                     }
                 },
             };
@@ -366,6 +1002,7 @@ fn write_shareable_enum(
 fn read_shareable_enum(
     shareable_ident: &syn::Ident,
     data_enum: &syn::DataEnum,
+    container: &ContainerAttributes,
 ) -> syn::Result<proc_macro2::TokenStream> {
     let syn::DataEnum { variants, .. } = data_enum;
 
@@ -376,33 +1013,50 @@ fn read_shareable_enum(
             let read_field = match &v.fields {
                 syn::Fields::Named(ref fields_named) => {
                     let entry_ident = quote! { #shareable_ident::#variant_ident };
-                    read_fields_named(&entry_ident, fields_named)?
+                    read_fields_named(&entry_ident, fields_named, container)?
                 }
                 syn::Fields::Unnamed(ref fields_unnamed) => {
                     let entry_ident = quote! { #shareable_ident::#variant_ident };
-                    read_fields_unnamed(&entry_ident, fields_unnamed)?
+                    read_fields_unnamed(&entry_ident, fields_unnamed, container)?
                 }
                 syn::Fields::Unit => {
                     quote! {::std::result::Result::Ok(#shareable_ident::#variant_ident) }
                 }
             };
 
-            let read_variant = quote! { stringify!(#variant_ident) => #read_field };
+            let (_, accepted) = parse_variant_attributes(v)?.names(variant_ident);
+            let read_field = at_result(
+                quote! { stringify!(#variant_ident) },
+                deny_trailing(read_field),
+            );
+            let read_variant = quote! { #(#accepted)|* => #read_field };
             Ok(read_variant)
         })
         .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
 
+    // Variants added by a newer build are read as the catch-all, if any.
+    let read_unknown = match parse_other_variant(data_enum)? {
+        Some(other) => {
+            let other_ident = &other.ident;
+            quote! { ::std::result::Result::Ok(#shareable_ident::#other_ident) }
+        }
+        None => quote! {
+            ::std::result::Result::Err(
+                __atlas_comms::port::ShareableError::bad_payload()
+                    .with_expected(concat!("variant of ", stringify!(#shareable_ident)))
+                    .with_found(variant_ident)
+            )
+        },
+    };
+
+    let read_variant_ident = expect_string(shift(), quote! { "variant" });
     let read = quote! {
-        let variant_ident = __payload
-            .shift()
-            .as_string()
-            .ok_or(crate::port::ShareableError::BadPayload)?;
+        let variant_ident = #read_variant_ident;
 
         match variant_ident.as_ref() {
             #(#read_variants,)*
-            _ =>::std::result::Result::Err(crate::port::ShareableError::BadPayload)
+            _ => #read_unknown
         }
-
     };
 
     Ok(read)
@@ -0,0 +1,134 @@
+use syn::spanned::Spanned;
+
+pub enum Container<'a> {
+    Option(&'a syn::Type),
+    Vec(&'a syn::Type),
+    Map(&'a syn::Type, &'a syn::Type),
+    Box(&'a syn::Type),
+    Tuple(Vec<&'a syn::Type>),
+    Array(&'a syn::Type),
+}
+
+pub const UNSUPPORTED_BUFFER: &str =
+    "unsupported buffer type, expected a Vec of u8, i8, u16, i16, u32, i32, f32 or f64";
+const UNSUPPORTED_MAP_KEY: &str = "unsupported map key, only String keys can be shared";
+const UNSUPPORTED_TYPE: &str =
+    "unsupported field type, shared fields must be owned: use a path, tuple, array or Box<T>";
+
+pub fn parse_container(ty: &syn::Type) -> Option<Container<'_>> {
+    let path = match ungroup(ty) {
+        syn::Type::Path(path) => path,
+        syn::Type::Tuple(tuple) => return Some(Container::Tuple(tuple.elems.iter().collect())),
+        syn::Type::Array(array) => return Some(Container::Array(&array.elem)),
+        _ => return None,
+    };
+    if path.qself.is_some() {
+        return None;
+    }
+
+    let segment = path.path.segments.last()?;
+    let syn::PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+    let arguments: Vec<&syn::Type> = arguments
+        .args
+        .iter()
+        .filter_map(|arg| match arg {
+            syn::GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        })
+        .collect();
+
+    match (segment.ident.to_string().as_ref(), arguments.as_slice()) {
+        ("Option", [inner]) => Some(Container::Option(inner)),
+        ("Vec", [inner]) => Some(Container::Vec(inner)),
+        ("HashMap", [key, value, ..]) => Some(Container::Map(key, value)),
+        ("Box", [inner]) => Some(Container::Box(inner)),
+        _ => None,
+    }
+}
+
+pub fn parse_buffer(ty: &syn::Type) -> Option<&'static str> {
+    let Some(Container::Vec(elem)) = parse_container(ty) else {
+        return None;
+    };
+    let syn::Type::Path(elem) = ungroup(elem) else {
+        return None;
+    };
+
+    let typed_array = match elem.path.get_ident()?.to_string().as_ref() {
+        "u8" => "Uint8Array",
+        "i8" => "Int8Array",
+        "u16" => "Uint16Array",
+        "i16" => "Int16Array",
+        "u32" => "Uint32Array",
+        "i32" => "Int32Array",
+        "f32" => "Float32Array",
+        "f64" => "Float64Array",
+        _ => return None,
+    };
+    Some(typed_array)
+}
+
+pub fn ungroup(mut ty: &syn::Type) -> &syn::Type {
+    loop {
+        ty = match ty {
+            syn::Type::Paren(paren) => &paren.elem,
+            syn::Type::Group(group) => &group.elem,
+            _ => return ty,
+        }
+    }
+}
+
+pub fn check_owned(ty: &syn::Type) -> syn::Result<()> {
+    match ungroup(ty) {
+        syn::Type::Path(path) => {
+            if let Some(qself) = &path.qself {
+                check_owned(&qself.ty)?;
+            }
+            for segment in &path.path.segments {
+                if let syn::PathArguments::AngleBracketed(arguments) = &segment.arguments {
+                    for arg in &arguments.args {
+                        if let syn::GenericArgument::Type(ty) = arg {
+                            check_owned(ty)?;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+        syn::Type::Tuple(tuple) => tuple.elems.iter().try_for_each(check_owned),
+        syn::Type::Array(array) => check_owned(&array.elem),
+        syn::Type::Macro(_) | syn::Type::Verbatim(_) => Ok(()),
+        ty => Err(syn::Error::new(ty.span(), UNSUPPORTED_TYPE)),
+    }
+}
+
+pub fn check_map_key(key: &syn::Type) -> syn::Result<()> {
+    let is_string = match ungroup(key) {
+        syn::Type::Path(path) => path.path.is_ident("String"),
+        _ => false,
+    };
+
+    if is_string {
+        Ok(())
+    } else {
+        Err(syn::Error::new(key.span(), UNSUPPORTED_MAP_KEY))
+    }
+}
+
+// Whether the type names the type being derived.
+pub fn is_self(shareable_ident: &syn::Ident, ty: &syn::Type) -> bool {
+    match ungroup(ty) {
+        syn::Type::Path(path) => {
+            path.qself.is_none()
+                && path
+                    .path
+                    .segments
+                    .last()
+                    .map(|segment| segment.ident == "Self" || segment.ident == *shareable_ident)
+                    .unwrap_or(false)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,527 @@
+use crate::{
+    attrs::{parse_attributes, parse_variant_attributes, Attributes, ContainerAttributes, Repr},
+    shareable::{list_fields, unnamed_ident, UNSUPPORTED_UNION},
+    types::{parse_buffer, parse_container, ungroup, Container, UNSUPPORTED_BUFFER},
+};
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+
+// Stands in for the name of a raw type in a declaration, which is only known
+// once the alias it may be written as is resolved.
+const CLASS: char = '\0';
+
+// Describes the value into_ts turns a type into, which is what JS is handed.
+// Enums become a map from "Type::Variant" tags to the value of each variant,
+// plus a union of [tag, value] pairs over that map.
+pub fn expand_typescript(
+    ast: &syn::DeriveInput,
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let shareable_ident = &ast.ident;
+    let params: Vec<String> = ast
+        .generics
+        .type_params()
+        .map(|param| param.ident.to_string())
+        .collect();
+    let generics = if params.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", params.join(", "))
+    };
+
+    let mut classes = Vec::new();
+    let declaration = match &ast.data {
+        syn::Data::Struct(data_struct) => match &data_struct.fields {
+            syn::Fields::Named(_) => format!(
+                "export interface {}{} {}\n",
+                shareable_ident,
+                generics,
+                fields_object(
+                    &data_struct.fields,
+                    container,
+                    &params,
+                    &mut classes,
+                    "\n\t",
+                    "\n"
+                )?
+            ),
+            fields => format!(
+                "export type {}{} = {};\n",
+                shareable_ident,
+                generics,
+                fields_value(fields, container, &params, &mut classes)?
+            ),
+        },
+        syn::Data::Enum(data_enum) => {
+            let mut entries = String::new();
+            for variant in &data_enum.variants {
+                let (name, _) = parse_variant_attributes(variant)?.names(&variant.ident);
+                entries.push_str(&format!(
+                    "\t'{}::{}': {};\n",
+                    shareable_ident,
+                    name,
+                    fields_value(&variant.fields, container, &params, &mut classes)?
+                ));
+            }
+
+            // Tags don't depend on the generics, any instance has the same keys.
+            let any = if params.is_empty() {
+                String::new()
+            } else {
+                format!("<{}>", vec!["unknown"; params.len()].join(", "))
+            };
+            format!(
+                "export interface {ident}Map{generics} {{\n{entries}}}\n\
+                 export type {ident}Tag = keyof {ident}Map{any};\n\
+                 export type {ident}{generics} = {{\n\
+                 \t[K in {ident}Tag]: [K, {ident}Map{generics}[K]];\n\
+                 }}[{ident}Tag];\n",
+                ident = shareable_ident,
+                generics = generics,
+                entries = entries,
+                any = any,
+            )
+        }
+        syn::Data::Union(_) => return Err(syn::Error::new(ast.span(), UNSUPPORTED_UNION)),
+    };
+
+    let pieces = declaration.split(CLASS).collect::<Vec<&str>>();
+    let (last, pieces) = pieces
+        .split_last()
+        .expect("split yields at least one piece");
+    let into_ts = into_ts(ast, container)?;
+
+    let (impl_generics, ty_generics, where_clause) = &ast.generics.split_for_impl();
+    let expanded = quote! {
+        impl #impl_generics
+            __atlas_comms::typescript::TypeScript for #shareable_ident #ty_generics
+            #where_clause
+        {
+            fn declaration() -> ::std::string::String {
+                let mut __declaration = ::std::string::String::new();
+                #(
+                    __declaration.push_str(#pieces);
+                    __declaration.push_str(
+                        &__atlas_comms::typescript::class_name::<#classes>()
+                    );
+                )*
+                __declaration.push_str(#last);
+                __declaration
+            }
+
+            fn into_ts<__V: __atlas_comms::value::Value>(
+                self
+            ) -> ::std::result::Result<__V, __atlas_comms::port::ShareableError> {
+                #into_ts
+            }
+        }
+    };
+
+    Ok(expanded)
+}
+
+fn into_ts(
+    ast: &syn::DeriveInput,
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let shareable_ident = &ast.ident;
+
+    match &ast.data {
+        syn::Data::Struct(data_struct) => {
+            let fields = &data_struct.fields;
+            let list_fields = list_fields(fields, container);
+            let destructure = match fields {
+                syn::Fields::Named(_) => quote! { let #shareable_ident { #list_fields } = self; },
+                syn::Fields::Unnamed(_) => quote! { let #shareable_ident(#list_fields) = self; },
+                syn::Fields::Unit => quote! {},
+            };
+            let value = fields_ts(fields, container)?;
+
+            Ok(quote! {
+                #destructure
+                let __value = #value;
+                ::std::result::Result::Ok(__value)
+            })
+        }
+        syn::Data::Enum(data_enum) => {
+            let variants = data_enum
+                .variants
+                .iter()
+                .map(|variant| {
+                    let variant_ident = &variant.ident;
+                    let (name, _) = parse_variant_attributes(variant)?.names(variant_ident);
+                    let tag = format!("{}::{}", shareable_ident, name);
+                    let list_fields = list_fields(&variant.fields, container);
+                    let pattern = match &variant.fields {
+                        syn::Fields::Named(_) => {
+                            quote! { #shareable_ident::#variant_ident { #list_fields } }
+                        }
+                        syn::Fields::Unnamed(_) => {
+                            quote! { #shareable_ident::#variant_ident(#list_fields) }
+                        }
+                        syn::Fields::Unit => quote! { #shareable_ident::#variant_ident },
+                    };
+                    let value = fields_ts(&variant.fields, container)?;
+
+                    Ok(quote! {
+                        #pattern => __V::array(::std::vec![__V::string(#tag), #value])
+                    })
+                })
+                .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
+
+            Ok(quote! {
+                ::std::result::Result::Ok(match self {
+                    #(#variants,)*
+                })
+            })
+        }
+        syn::Data::Union(_) => Err(syn::Error::new(ast.span(), UNSUPPORTED_UNION)),
+    }
+}
+
+// Builds the value fields_value declares, from fields bound by list_fields.
+fn fields_ts(
+    fields: &syn::Fields,
+    container: &ContainerAttributes,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let mut values = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let field_attrs = parse_attributes(field, container)?;
+        if field_attrs.skip {
+            continue;
+        }
+
+        let field_ident = field.ident.clone().unwrap_or(unnamed_ident(i, field));
+        let (name, _) = field_attrs.names(&field_ident);
+        let value = ts_value(&field.ty, &field_ident, &field_attrs, 0)?;
+        values.push((name, value));
+    }
+
+    Ok(match (fields, values.as_slice()) {
+        (syn::Fields::Named(_), values) => {
+            let fields = values
+                .iter()
+                .map(|(name, value)| quote! { (#name, #value) });
+            quote! { __V::object(::std::vec![#(#fields),*]) }
+        }
+        (_, []) => quote! { __V::undefined() },
+        (syn::Fields::Unnamed(_), [(_, value)]) if fields.len() == 1 => value.clone(),
+        (_, values) => {
+            let values = values.iter().map(|(_, value)| value);
+            quote! { __V::array(::std::vec![#(#values),*]) }
+        }
+    })
+}
+
+// Follows ts_type, without anything to transfer since the value stays put.
+fn ts_value(
+    ty: &syn::Type,
+    value: &syn::Ident,
+    attrs: &Attributes,
+    depth: usize,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some(with) = &attrs.with {
+        return Ok(quote! { #with::encode(&#value, &mut ::std::vec::Vec::new())? });
+    }
+
+    if attrs.repr == Repr::Serde {
+        return Ok(quote! { __V::serialize(&#value)? });
+    }
+
+    if let (Repr::Buffer, Some(_)) = (&attrs.repr, parse_buffer(ty)) {
+        return Ok(quote! { __V::buffer(&#value[..]) });
+    }
+
+    let item = format_ident!("__item{}", depth);
+    let expanded = match parse_container(ty) {
+        Some(Container::Option(inner)) => {
+            let item_value = ts_value(inner, &item, attrs, depth + 1)?;
+            quote! {
+                match #value {
+                    ::std::option::Option::Some(#item) => #item_value,
+                    ::std::option::Option::None => __V::undefined(),
+                }
+            }
+        }
+        Some(Container::Vec(inner)) | Some(Container::Array(inner)) => {
+            let array = format_ident!("__array{}", depth);
+            let item_value = ts_value(inner, &item, attrs, depth + 1)?;
+            quote! {{
+                let mut #array = ::std::vec::Vec::new();
+                for #item in #value {
+                    #array.push(#item_value);
+                }
+                __V::array(#array)
+            }}
+        }
+        Some(Container::Map(_, inner)) => {
+            let map = format_ident!("__map{}", depth);
+            let key = format_ident!("__key{}", depth);
+            let item_value = ts_value(inner, &item, attrs, depth + 1)?;
+            quote! {{
+                let mut #map = ::std::vec::Vec::new();
+                for (#key, #item) in #value {
+                    #map.push((__V::string(&#key), #item_value));
+                }
+                __V::map(#map)
+            }}
+        }
+        Some(Container::Box(inner)) => {
+            let item_value = ts_value(inner, &item, attrs, depth + 1)?;
+            quote! {{
+                let #item = *#value;
+                #item_value
+            }}
+        }
+        Some(Container::Tuple(elems)) => {
+            let items = (0..elems.len())
+                .map(|i| format_ident!("__item{}_{}", depth, i))
+                .collect::<Vec<_>>();
+            let item_values = elems
+                .iter()
+                .zip(&items)
+                .map(|(elem, item)| ts_value(elem, item, attrs, depth + 1))
+                .collect::<syn::Result<Vec<proc_macro2::TokenStream>>>()?;
+            quote! {{
+                let (#(#items,)*) = #value;
+                __V::array(::std::vec![#(#item_values),*])
+            }}
+        }
+        None => match attrs.repr {
+            Repr::Raw => quote! { __V::raw(#value)? },
+            Repr::Shareable => quote! {
+                __atlas_comms::typescript::TypeScript::into_ts(#value)?
+            },
+            Repr::Buffer => return Err(syn::Error::new(ty.span(), UNSUPPORTED_BUFFER)),
+            Repr::Serde => unreachable!(),
+        },
+    };
+
+    Ok(expanded)
+}
+
+// Unit is undefined, a single unnamed field is its own value, more than one
+// are a tuple and named fields are an object.
+fn fields_value(
+    fields: &syn::Fields,
+    container: &ContainerAttributes,
+    params: &[String],
+    classes: &mut Vec<syn::Type>,
+) -> syn::Result<String> {
+    if let syn::Fields::Named(_) = fields {
+        return fields_object(fields, container, params, classes, " ", " ");
+    }
+
+    let mut types = Vec::new();
+    for field in fields {
+        let field_attrs = parse_attributes(field, container)?;
+        if !field_attrs.skip {
+            types.push(ts_type(&field.ty, &field_attrs, params, classes));
+        }
+    }
+
+    Ok(match types.as_slice() {
+        [] => "undefined".into(),
+        [ty] if matches!(fields, syn::Fields::Unnamed(_)) && fields.len() == 1 => ty.clone(),
+        types => format!("[{}]", types.join(", ")),
+    })
+}
+
+fn fields_object(
+    fields: &syn::Fields,
+    container: &ContainerAttributes,
+    params: &[String],
+    classes: &mut Vec<syn::Type>,
+    separator: &str,
+    end: &str,
+) -> syn::Result<String> {
+    let mut object = String::from("{");
+    for field in fields {
+        let field_attrs = parse_attributes(field, container)?;
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+        if field_attrs.skip {
+            continue;
+        }
+
+        let (name, _) = field_attrs.names(ident);
+        let ty = ts_type(&field.ty, &field_attrs, params, classes);
+        object.push_str(&format!("{}{}: {};", separator, name, ty));
+    }
+    object.push_str(end);
+    object.push('}');
+
+    Ok(object)
+}
+
+fn ts_type(
+    ty: &syn::Type,
+    attrs: &Attributes,
+    params: &[String],
+    classes: &mut Vec<syn::Type>,
+) -> String {
+    // Whatever the codec returns.
+    if attrs.with.is_some() {
+        return "unknown".into();
+    }
+
+    if attrs.repr == Repr::Serde {
+        return serde_type(ty, params);
+    }
+
+    if let (Repr::Buffer, Some(typed_array)) = (&attrs.repr, parse_buffer(ty)) {
+        return typed_array.into();
+    }
+
+    match parse_container(ty) {
+        Some(Container::Option(inner)) => {
+            format!("{} | undefined", ts_type(inner, attrs, params, classes))
+        }
+        Some(Container::Vec(inner)) | Some(Container::Array(inner)) => {
+            array_type(ts_type(inner, attrs, params, classes))
+        }
+        Some(Container::Map(_, inner)) => {
+            format!("Map<string, {}>", ts_type(inner, attrs, params, classes))
+        }
+        Some(Container::Box(inner)) => ts_type(inner, attrs, params, classes),
+        Some(Container::Tuple(elems)) => tuple_type(
+            elems
+                .into_iter()
+                .map(|elem| ts_type(elem, attrs, params, classes))
+                .collect(),
+        ),
+        None => match attrs.repr {
+            Repr::Raw => match (primitive_type(ty), param_type(ty, params)) {
+                (Some(primitive), _) => primitive.into(),
+                (None, Some(param)) => param,
+                // Named after the JS class, a Rust alias would be meaningless.
+                (None, None) => {
+                    classes.push(ty.clone());
+                    CLASS.into()
+                }
+            },
+            _ => named_type(ty, params, |arg| ts_type(arg, attrs, params, classes)),
+        },
+    }
+}
+
+// The shape serde_wasm_bindgen gives the type. Types it doesn't know are
+// declared as unknown.
+fn serde_type(ty: &syn::Type, params: &[String]) -> String {
+    if let Some(primitive) = primitive_type(ty) {
+        return primitive.into();
+    }
+
+    let path = match ungroup(ty) {
+        syn::Type::Path(path) if path.qself.is_none() => path,
+        syn::Type::Tuple(tuple) if tuple.elems.is_empty() => return "undefined".into(),
+        syn::Type::Tuple(tuple) => {
+            return tuple_type(
+                tuple
+                    .elems
+                    .iter()
+                    .map(|elem| serde_type(elem, params))
+                    .collect(),
+            )
+        }
+        syn::Type::Array(array) => return array_type(serde_type(&array.elem, params)),
+        _ => return "unknown".into(),
+    };
+    if let Some(param) = param_type(ty, params) {
+        return param;
+    }
+
+    let Some(segment) = path.path.segments.last() else {
+        return "unknown".into();
+    };
+    let arguments: Vec<String> = match &segment.arguments {
+        syn::PathArguments::AngleBracketed(arguments) => arguments
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                syn::GenericArgument::Type(ty) => Some(serde_type(ty, params)),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    match (segment.ident.to_string().as_ref(), arguments.as_slice()) {
+        ("Option", [inner]) => format!("{} | undefined", inner),
+        ("Vec" | "VecDeque" | "HashSet" | "BTreeSet", [inner]) => array_type(inner.clone()),
+        ("HashMap" | "BTreeMap", [key, value, ..]) => format!("Map<{}, {}>", key, value),
+        ("Box" | "Rc" | "Arc", [inner]) => inner.clone(),
+        _ => "unknown".into(),
+    }
+}
+
+fn primitive_type(ty: &syn::Type) -> Option<&'static str> {
+    let syn::Type::Path(path) = ungroup(ty) else {
+        return None;
+    };
+
+    let primitive = match path.path.get_ident()?.to_string().as_ref() {
+        "bool" => "boolean",
+        "u8" | "u16" | "u32" | "u64" | "u128" | "usize" | "i8" | "i16" | "i32" | "i64" | "i128"
+        | "isize" | "f32" | "f64" => "number",
+        "char" | "String" | "str" => "string",
+        "JsValue" => "unknown",
+        _ => return None,
+    };
+    Some(primitive)
+}
+
+fn param_type(ty: &syn::Type, params: &[String]) -> Option<String> {
+    let syn::Type::Path(path) = ungroup(ty) else {
+        return None;
+    };
+
+    let ident = path.path.get_ident()?.to_string();
+    params.contains(&ident).then_some(ident)
+}
+
+// Shareable types are declared under their own name.
+fn named_type(
+    ty: &syn::Type,
+    params: &[String],
+    mut arg_type: impl FnMut(&syn::Type) -> String,
+) -> String {
+    if let Some(param) = param_type(ty, params) {
+        return param;
+    }
+
+    let syn::Type::Path(path) = ungroup(ty) else {
+        return "unknown".into();
+    };
+    let Some(segment) = path.path.segments.last() else {
+        return "unknown".into();
+    };
+
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(arguments) => {
+            let arguments: Vec<String> = arguments
+                .args
+                .iter()
+                .filter_map(|arg| match arg {
+                    syn::GenericArgument::Type(ty) => Some(arg_type(ty)),
+                    _ => None,
+                })
+                .collect();
+            format!("{}<{}>", segment.ident, arguments.join(", "))
+        }
+        _ => segment.ident.to_string(),
+    }
+}
+
+fn array_type(inner: String) -> String {
+    if inner.contains('|') {
+        format!("({})[]", inner)
+    } else {
+        format!("{}[]", inner)
+    }
+}
+
+fn tuple_type(elems: Vec<String>) -> String {
+    format!("[{}]", elems.join(", "))
+}
@@ -1,8 +1,26 @@
 use log::trace;
+use std::fmt;
 use web_sys::OffscreenCanvas;
 use wgpu::{Backends, Instance, InstanceDescriptor, RequestAdapterOptions};
 
-pub async fn list_adapters(surface: OffscreenCanvas) {
+#[derive(Debug)]
+pub enum GraphicsError {
+    Surface(String),
+    NoAdapter,
+}
+
+impl fmt::Display for GraphicsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphicsError::Surface(reason) => write!(f, "failed to create a surface: {}", reason),
+            GraphicsError::NoAdapter => write!(f, "no adapter is compatible with the surface"),
+        }
+    }
+}
+
+impl std::error::Error for GraphicsError {}
+
+pub async fn list_adapters(surface: OffscreenCanvas) -> Result<(), GraphicsError> {
     let instance = Instance::new(InstanceDescriptor {
         backends: Backends::BROWSER_WEBGPU,
         dx12_shader_compiler: Default::default(),
@@ -10,7 +28,7 @@ pub async fn list_adapters(surface: OffscreenCanvas) {
 
     let surface = instance
         .create_surface_from_offscreen_canvas(surface)
-        .expect("surface");
+        .map_err(|error| GraphicsError::Surface(error.to_string()))?;
 
     let adapter = instance
         .request_adapter(&RequestAdapterOptions {
@@ -19,9 +37,11 @@ pub async fn list_adapters(surface: OffscreenCanvas) {
             force_fallback_adapter: false,
         })
         .await
-        .expect("adapter");
+        .ok_or(GraphicsError::NoAdapter)?;
     trace!(
         "        [server]: found a wgpu adapter: {:?}",
         adapter.get_info()
     );
+
+    Ok(())
 }
@@ -1,88 +1,145 @@
 use atlas_comms::{
     client::ClientMessage,
-    port::Port,
-    server::{ServerEvent, ServerMessage, ServerResponse},
-    Payload,
+    heartbeat::Watch,
+    port::{Port, TypedPort},
+    rpc::{Cancellation, Request, RpcServer},
+    server::{ServerError, ServerEvent, ServerMessage, ServerResponse},
 };
-use log::trace;
-use tokio::sync::mpsc::unbounded_channel;
+use atlas_graphics::GraphicsError;
+use log::{trace, warn};
+use std::time::Duration;
 use wasm_bindgen::prelude::*;
-use web_sys::{DedicatedWorkerGlobalScope, MessageEvent};
+use web_sys::DedicatedWorkerGlobalScope;
 
 pub use atlas_comms::init_output;
 pub use wasm_bindgen_rayon::init_thread_pool;
 
 #[wasm_bindgen]
 pub struct AtlasServer {
-    _scope: DedicatedWorkerGlobalScope,
     counter: u8,
-    wires: Vec<Port>,
-    port: Port,
+    wires: Vec<(TypedPort<ClientMessage, ServerEvent>, Watch)>,
+    port: RpcServer<ClientMessage, ServerResponse>,
 }
 
 #[wasm_bindgen]
 impl AtlasServer {
     #[wasm_bindgen(constructor)]
     pub fn new(scope: DedicatedWorkerGlobalScope) -> Self {
+        let port = RpcServer::new(Port::wrap(Box::new(scope)));
+        port.forward_panics();
+        // Heartbeats, answered even while a request is being worked on.
+        port.respond_immediately(|message| {
+            matches!(message, ClientMessage::Ping).then_some(ServerResponse::Ok(ServerMessage::Ok))
+        });
+
         Self {
-            _scope: scope.clone(),
             counter: 0,
             wires: Vec::new(),
-            port: Port::wrap(Box::new(scope)),
+            port,
         }
     }
 
-    pub async fn listen(&mut self) {
-        let (tx, mut rx) = unbounded_channel();
-
-        let listener = self
-            .port
-            .add_listener(Closure::new(move |event: MessageEvent| {
-                tx.send(event).expect("Server channel should be open.");
-            }));
+    pub async fn listen(&mut self) -> Result<(), JsError> {
+        let mut requests = self.port.requests()?;
+        // Only sent once requests can be read, clients wait for it.
+        let hello = atlas_comms::hello();
+        trace!("[server]->client: {:?}", hello);
+        self.port.port().post(hello.to_value())?;
 
-        while let Some(event) = rx.recv().await {
-            let payload: Payload<ClientMessage> = event.data().try_into().unwrap();
-            trace!("client->[server]: {:?}", payload);
-
-            let Payload { id, message } = payload;
-            let res = match message {
-                ClientMessage::Ping => ServerResponse::Ok(ServerMessage::Ok),
-                ClientMessage::Query => {
-                    self.push_event(ServerEvent::Count(self.counter));
-                    ServerResponse::Ok(ServerMessage::Ok)
+        while let Some(request) = requests.recv().await {
+            // Anything can post to the worker, so bad messages are dropped.
+            let request = match request {
+                Ok(request) => request,
+                Err(error) => {
+                    warn!("client->[server]: {}", error);
+                    continue;
                 }
-                ClientMessage::Inc => {
-                    self.counter += 1;
-
-                    self.push_event(ServerEvent::Count(self.counter));
-                    ServerResponse::Ok(ServerMessage::Ok)
-                }
-                ClientMessage::Dec => {
-                    self.counter -= 1;
+            };
+            trace!("client->[server]: {:?}", request);
 
-                    self.push_event(ServerEvent::Count(self.counter));
-                    ServerResponse::Ok(ServerMessage::Ok)
-                }
-                ClientMessage::Attach(surface) => {
-                    atlas_graphics::list_adapters(surface).await;
-                    ServerResponse::Ok(ServerMessage::Ok)
-                }
-                ClientMessage::WireUp(port) => {
-                    self.wires.push(Port::wrap(Box::new(port)));
-                    ServerResponse::Ok(ServerMessage::Ok)
+            let Request {
+                id,
+                message,
+                cancellation,
+            } = request;
+            let res = match self.handle(message, &cancellation).await {
+                Ok(message) => ServerResponse::Ok(message),
+                Err(error) => {
+                    warn!("[server]: request {} failed: {}", id, error);
+                    ServerResponse::Err(error)
                 }
             };
 
-            self.port.send(Payload { id, message: res });
+            // A response that can't be sent only fails its own request.
+            if let Err(error) = self.port.respond(id, res) {
+                warn!("[server]->client: {}", error);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle(
+        &mut self,
+        message: ClientMessage,
+        cancellation: &Cancellation,
+    ) -> Result<ServerMessage, ServerError> {
+        match message {
+            // Beats only mean something on a wire.
+            ClientMessage::Ping | ClientMessage::Beat(_) => {}
+            ClientMessage::Query => self.push_event(ServerEvent::Count(self.counter)),
+            ClientMessage::Inc => {
+                self.counter = self.counter.checked_add(1).ok_or(ServerError::OutOfRange)?;
+                self.push_event(ServerEvent::Count(self.counter));
+            }
+            ClientMessage::Dec => {
+                self.counter = self.counter.checked_sub(1).ok_or(ServerError::OutOfRange)?;
+                self.push_event(ServerEvent::Count(self.counter));
+            }
+            ClientMessage::Attach(surface) => {
+                // Can hang looking for an adapter, so the client may give up.
+                let adapters = atlas_graphics::list_adapters(surface);
+                cancellation
+                    .or_cancelled(adapters)
+                    .await
+                    .ok_or(ServerError::Cancelled)?
+                    .map_err(|error| match error {
+                        GraphicsError::Surface(reason) => ServerError::Surface(reason),
+                        GraphicsError::NoAdapter => ServerError::NoAdapter,
+                    })?;
+            }
+            ClientMessage::WireUp(port) => {
+                let wire = TypedPort::new(Port::wrap(Box::new(port)));
+                let watch = Watch::new(&wire, |message| match message {
+                    ClientMessage::Beat(interval) => {
+                        Some(Duration::from_millis((*interval).into()))
+                    }
+                    _ => None,
+                })
+                .map_err(|error| ServerError::Wire(error.to_string()))?;
+                self.wires.push((wire, watch));
+            }
+            ClientMessage::Unknown => return Err(ServerError::Unsupported),
         }
 
-        listener.clear();
+        Ok(ServerMessage::Ok)
     }
 
-    fn push_event(&self, event: ServerEvent) {
-        for wire in &self.wires {
-            wire.send(event.clone());
+    fn push_event(&mut self, event: ServerEvent) {
+        // Clients beat on their wires, ones that stopped are gone.
+        self.wires.retain(|(wire, watch)| {
+            let lost = watch.is_lost();
+            if lost {
+                trace!("[server]->wire: dropped a lost wire");
+                wire.port().close();
+            }
+            !lost
+        });
+
+        for (wire, _) in &self.wires {
+            if let Err(error) = wire.send(event) {
+                warn!("[server]->wire: {}", error);
+            }
         }
     }
 }
@@ -1,14 +1,6 @@
-export default function spawn(Worker: new () => Worker): Promise<Worker> {
-	return new Promise((resolve, _reject) => {
-		const worker = new Worker();
+import { AtlasClient } from '$atlas/client';
 
-		worker.onmessage = (message) => {
-			if (message.data === 'ready') {
-				worker.onmessage = null;
-				resolve(worker);
-			} else {
-				console.warn('Worker message posted before initialization.', message);
-			}
-		};
-	});
+// Resolves once the server announced itself, rejects if it isn't compatible.
+export default function spawn(Worker: new () => Worker): Promise<AtlasClient> {
+	return AtlasClient.connect(new Worker());
 }
@@ -4,7 +4,5 @@ await init();
 initOutput();
 await initThreadPool(navigator.hardwareConcurrency);
 
-postMessage('ready');
-
 const atlas = new AtlasServer(self);
 await atlas.listen();